@@ -0,0 +1,360 @@
+//! Derive macro for the `vec_storage_reuse` crate. See `vec_storage_reuse::ReuseStorage`.
+
+extern crate proc_macro;
+
+use proc_macro::{Delimiter, Group, Ident, Spacing, TokenStream, TokenTree};
+
+/// Generates a storage struct for a "workspace" struct whose fields are all `Vec`s borrowing with the
+/// lifetime parameters of the struct.
+///
+/// For a `struct Workspace<'a>`, this generates:
+/// - `WorkspaceStorage`, which holds a `VecStorageForReuse` for each field, with the lifetimes replaced by
+///   `'static`,
+/// - `WorkspaceStorage::reuse`, which returns a `WorkspaceStorageReuse<'_, 'a>`. That guard implements
+///   `DerefMut<Target = Workspace<'a>>`, whose fields reuse the allocations of the storage, and puts all of them
+///   back in place in the storage once dropped.
+///
+/// ```
+/// use vec_storage_reuse::ReuseStorage;
+///
+/// #[derive(ReuseStorage)]
+/// struct Workspace<'a> {
+///     tokens: Vec<&'a str>,
+///     spans: Vec<(usize, &'a [u8])>,
+/// }
+///
+/// let mut storage = WorkspaceStorage::new();
+/// for chunk in ["a b", "c d e"] {
+///     let chunk = chunk.to_owned(); // only lives this scope
+///     let mut workspace = storage.reuse();
+///     workspace.tokens.extend(chunk.split(' '));
+///     workspace.spans.push((0, chunk.as_bytes()));
+/// }
+/// ```
+#[proc_macro_derive(ReuseStorage)]
+pub fn derive_reuse_storage(input: TokenStream) -> TokenStream {
+    match Workspace::parse(input) {
+        Ok(workspace) => workspace.expand(),
+        Err(message) => format!("compile_error!({:?});", message),
+    }
+    .parse()
+    .expect("Generated code should be valid tokens")
+}
+
+struct Workspace {
+    vis: String,
+    name: String,
+    /// Names of the lifetime parameters, including the `'`
+    lifetimes: Vec<String>,
+    /// Lifetime parameters as written on the struct, including bounds
+    generics: String,
+    fields: Vec<Field>,
+}
+
+struct Field {
+    name: String,
+    /// Element type of the `Vec`
+    element: Vec<TokenTree>,
+}
+
+type Result<T> = std::result::Result<T, String>;
+
+impl Workspace {
+    fn parse(input: TokenStream) -> Result<Self> {
+        let mut tokens = input.into_iter().peekable();
+        skip_attributes(&mut tokens);
+        let vis = parse_visibility(&mut tokens);
+        match tokens.next() {
+            Some(TokenTree::Ident(ident)) if ident.to_string() == "struct" => {}
+            _ => return Err("ReuseStorage can only be derived on structs".into()),
+        }
+        let name = match tokens.next() {
+            Some(TokenTree::Ident(ident)) => ident.to_string(),
+            _ => return Err("Expected struct name".into()),
+        };
+
+        let mut lifetimes = Vec::new();
+        let mut generics = Vec::new();
+        if matches!(tokens.peek(), Some(TokenTree::Punct(p)) if p.as_char() == '<') {
+            tokens.next();
+            let mut depth = 0;
+            let mut expecting_param = true;
+            loop {
+                let token = tokens.next().ok_or("Unterminated generics")?;
+                match &token {
+                    TokenTree::Punct(p) if p.as_char() == '<' => depth += 1,
+                    TokenTree::Punct(p) if p.as_char() == '>' => {
+                        if depth == 0 {
+                            break;
+                        }
+                        depth -= 1;
+                    }
+                    TokenTree::Punct(p) if p.as_char() == ',' && depth == 0 => {
+                        expecting_param = true;
+                        generics.push(token);
+                        continue;
+                    }
+                    TokenTree::Punct(p) if p.as_char() == '\'' && expecting_param => {
+                        match tokens.next() {
+                            Some(TokenTree::Ident(ident)) => {
+                                lifetimes.push(format!("'{}", ident));
+                                generics.push(token);
+                                generics.push(TokenTree::Ident(ident));
+                            }
+                            _ => return Err("Expected lifetime name".into()),
+                        }
+                        expecting_param = false;
+                        continue;
+                    }
+                    _ if expecting_param => return Err(
+                        "ReuseStorage can only be derived on structs with lifetime parameters only"
+                            .into(),
+                    ),
+                    _ => {}
+                }
+                generics.push(token);
+            }
+        }
+
+        let body = match tokens.next() {
+            Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace => {
+                group.stream()
+            }
+            Some(TokenTree::Ident(ident)) if ident.to_string() == "where" => {
+                return Err("ReuseStorage doesn't support where clauses".into())
+            }
+            _ => {
+                return Err("ReuseStorage can only be derived on structs with named fields".into())
+            }
+        };
+
+        let fields = split_top_level_commas(body)
+            .into_iter()
+            .map(Field::parse)
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            vis,
+            name,
+            lifetimes,
+            generics: generics.into_iter().collect::<TokenStream>().to_string(),
+            fields,
+        })
+    }
+
+    fn expand(&self) -> String {
+        let Self {
+            vis,
+            name,
+            generics,
+            ..
+        } = self;
+        let storage = format!("{}Storage", name);
+        let guard = format!("{}StorageReuse", name);
+        let lifetimes = self.lifetimes.join(", ");
+
+        let mut storage_fields = String::new();
+        let mut new_fields = String::new();
+        let mut take_fields = String::new();
+        let mut put_back_fields = String::new();
+        for Field {
+            name: field,
+            element,
+        } in &self.fields
+        {
+            let static_element = self.with_static_lifetimes(element.iter().cloned().collect());
+            storage_fields += &format!(
+                "{}: ::vec_storage_reuse::VecStorageForReuse<{}>,",
+                field, static_element
+            );
+            new_fields += &format!("{}: ::vec_storage_reuse::VecStorageForReuse::new(),", field);
+            take_fields += &format!(
+                "{field}: ::vec_storage_reuse::__private::recycle(
+                    ::std::mem::take(&mut self.{field}).into_inner()
+                ),",
+                field = field
+            );
+            put_back_fields += &format!(
+                "self.storage.{field} = ::vec_storage_reuse::VecStorageForReuse::from_vec(
+                    ::vec_storage_reuse::__private::recycle(::std::mem::take(&mut self.inner.{field}))
+                );",
+                field = field
+            );
+        }
+
+        format!(
+            "
+            /// Storage for the allocations of the `Vec`s of a `{name}`
+            {vis} struct {storage} {{
+                {storage_fields}
+            }}
+
+            impl {storage} {{
+                {vis} fn new() -> Self {{
+                    Self {{ {new_fields} }}
+                }}
+
+                /// Provides a `{name}` that reuses the allocations of the storage
+                {vis} fn reuse<{lifetimes}>(&mut self) -> {guard}<'_, {lifetimes}> {{
+                    {guard} {{
+                        inner: {name} {{ {take_fields} }},
+                        storage: self,
+                    }}
+                }}
+            }}
+
+            impl ::std::default::Default for {storage} {{
+                fn default() -> Self {{
+                    Self::new()
+                }}
+            }}
+
+            /// Implements `DerefMut<Target = {name}>`, and puts the allocations back in place
+            /// in the source `{storage}` once dropped
+            {vis} struct {guard}<'__storage, {generics}> {{
+                storage: &'__storage mut {storage},
+                inner: {name}<{lifetimes}>,
+            }}
+
+            impl<{generics}> ::std::ops::Drop for {guard}<'_, {lifetimes}> {{
+                fn drop(&mut self) {{
+                    {put_back_fields}
+                }}
+            }}
+
+            impl<{generics}> ::std::ops::Deref for {guard}<'_, {lifetimes}> {{
+                type Target = {name}<{lifetimes}>;
+                fn deref(&self) -> &Self::Target {{
+                    &self.inner
+                }}
+            }}
+            impl<{generics}> ::std::ops::DerefMut for {guard}<'_, {lifetimes}> {{
+                fn deref_mut(&mut self) -> &mut Self::Target {{
+                    &mut self.inner
+                }}
+            }}
+            ",
+            vis = vis,
+            name = name,
+            storage = storage,
+            guard = guard,
+            generics = generics,
+            lifetimes = lifetimes,
+            storage_fields = storage_fields,
+            new_fields = new_fields,
+            take_fields = take_fields,
+            put_back_fields = put_back_fields,
+        )
+    }
+
+    /// Replaces the lifetime parameters of the struct by `'static` in the given type
+    fn with_static_lifetimes(&self, tokens: TokenStream) -> TokenStream {
+        let mut out = Vec::new();
+        let mut tokens = tokens.into_iter().peekable();
+        while let Some(token) = tokens.next() {
+            match token {
+                TokenTree::Punct(ref p) if p.as_char() == '\'' => {
+                    out.push(token);
+                    if let Some(TokenTree::Ident(ident)) = tokens.peek() {
+                        if self.lifetimes.contains(&format!("'{}", ident)) {
+                            let ident = Ident::new("static", ident.span());
+                            tokens.next();
+                            out.push(TokenTree::Ident(ident));
+                        }
+                    }
+                }
+                TokenTree::Group(group) => out.push(TokenTree::Group(Group::new(
+                    group.delimiter(),
+                    self.with_static_lifetimes(group.stream()),
+                ))),
+                token => out.push(token),
+            }
+        }
+        out.into_iter().collect()
+    }
+}
+
+impl Field {
+    fn parse(tokens: Vec<TokenTree>) -> Result<Self> {
+        let mut tokens = tokens.into_iter().peekable();
+        skip_attributes(&mut tokens);
+        parse_visibility(&mut tokens);
+        let name = match tokens.next() {
+            Some(TokenTree::Ident(ident)) => ident.to_string(),
+            _ => return Err("Expected field name".into()),
+        };
+        match tokens.next() {
+            Some(TokenTree::Punct(p)) if p.as_char() == ':' => {}
+            _ => return Err(format!("Expected `:` after field `{}`", name)),
+        }
+        let not_a_vec = || format!("Field `{}` should be a `Vec<_>`", name);
+        match tokens.next() {
+            Some(TokenTree::Ident(ident)) if ident.to_string() == "Vec" => {}
+            _ => return Err(not_a_vec()),
+        }
+        match tokens.next() {
+            Some(TokenTree::Punct(p)) if p.as_char() == '<' => {}
+            _ => return Err(not_a_vec()),
+        }
+        let mut element: Vec<TokenTree> = tokens.collect();
+        match element.pop() {
+            Some(TokenTree::Punct(p)) if p.as_char() == '>' => {}
+            _ => return Err(not_a_vec()),
+        }
+        Ok(Self { name, element })
+    }
+}
+
+fn skip_attributes(tokens: &mut std::iter::Peekable<impl Iterator<Item = TokenTree>>) {
+    while matches!(tokens.peek(), Some(TokenTree::Punct(p)) if p.as_char() == '#') {
+        tokens.next();
+        tokens.next();
+    }
+}
+
+fn parse_visibility(tokens: &mut std::iter::Peekable<impl Iterator<Item = TokenTree>>) -> String {
+    match tokens.peek() {
+        Some(TokenTree::Ident(ident)) if ident.to_string() == "pub" => {
+            let mut vis = tokens.next().unwrap().to_string();
+            if matches!(tokens.peek(), Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Parenthesis)
+            {
+                vis += &tokens.next().unwrap().to_string();
+            }
+            vis
+        }
+        _ => String::new(),
+    }
+}
+
+/// Splits the fields of a struct, ignoring the commas in generic arguments
+fn split_top_level_commas(stream: TokenStream) -> Vec<Vec<TokenTree>> {
+    let mut fields = Vec::new();
+    let mut current = Vec::new();
+    let mut depth = 0usize;
+    let mut previous_is_joint_dash = false;
+    for token in stream {
+        let mut is_joint_dash = false;
+        match &token {
+            TokenTree::Punct(p) if p.as_char() == '<' => depth += 1,
+            // Don't mistake the `>` of `->` for the end of generic arguments
+            TokenTree::Punct(p) if p.as_char() == '>' && !previous_is_joint_dash => {
+                depth = depth.saturating_sub(1)
+            }
+            TokenTree::Punct(p) if p.as_char() == '-' && p.spacing() == Spacing::Joint => {
+                is_joint_dash = true
+            }
+            TokenTree::Punct(p) if p.as_char() == ',' && depth == 0 => {
+                fields.push(std::mem::take(&mut current));
+                previous_is_joint_dash = false;
+                continue;
+            }
+            _ => {}
+        }
+        previous_is_joint_dash = is_joint_dash;
+        current.push(token);
+    }
+    if !current.is_empty() {
+        fields.push(current);
+    }
+    fields
+}
@@ -0,0 +1,153 @@
+use crate::VecStorageForReuse;
+
+use std::{
+    cell::{Cell, RefCell},
+    mem,
+    ops::Drop,
+    ptr::NonNull,
+    slice,
+};
+
+impl VecStorageForReuse<u8> {
+    /// Uses the inner byte storage as a `ScratchArena`, to allocate slices of several types in the same
+    /// allocation
+    /// ```
+    /// # use vec_storage_reuse::VecStorageForReuse;
+    /// let mut scratch_storage: VecStorageForReuse<u8> = VecStorageForReuse::with_capacity(1024);
+    ///
+    /// for line in ["a b c", "d e"] {
+    ///     let line = line.to_owned(); // only lives this scope
+    ///     let arena = scratch_storage.reuse_as_arena();
+    ///     let words = arena.alloc_slice::<&str>(line.split(' ').count());
+    ///     for (slot, word) in words.iter_mut().zip(line.split(' ')) {
+    ///         *slot = word;
+    ///     }
+    ///     let lengths = arena.alloc_from_iter(words.iter().map(|word| word.len() as u32));
+    ///     let seen = arena.alloc_slice::<bool>(words.len());
+    ///     seen[0] = true;
+    ///     assert_eq!(lengths.len(), words.len());
+    /// } // Everything allocated in the arena is freed at once
+    /// ```
+    pub fn reuse_as_arena(&mut self) -> ScratchArena<'_> {
+        let storage = &mut self.inner;
+        // The storage is empty, so the whole allocation is available
+        let chunk = match NonNull::new(storage.as_mut_ptr()) {
+            Some(ptr) => (ptr, storage.capacity()),
+            None => (NonNull::dangling(), 0),
+        };
+        ScratchArena {
+            chunk: Cell::new(chunk),
+            overflow: RefCell::new(Vec::new()),
+            storage,
+        }
+    }
+}
+
+/// Bump allocator in the allocation of a `VecStorageForReuse<u8>`, which frees everything it allocated at once when
+/// dropped
+///
+/// Allocations that don't fit in the storage go to additional chunks. When the arena is dropped, the storage is
+/// grown so that all the bytes used in this round fit in it next time.
+///
+/// The values allocated in the arena are never dropped: they should not own resources.
+pub struct ScratchArena<'s> {
+    /// Start and length of the free part of the current chunk
+    chunk: Cell<(NonNull<u8>, usize)>,
+    /// Chunks allocated once the storage was full
+    overflow: RefCell<Vec<Vec<u8>>>,
+    /// Only kept borrowed so that its allocation isn't touched while the arena uses it
+    storage: &'s mut Vec<u8>,
+}
+
+impl ScratchArena<'_> {
+    /// Allocates a slice of `n` default values
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_slice<T: Default>(&self, n: usize) -> &mut [T] {
+        let ptr = self.alloc_uninit::<T>(n);
+        for i in 0..n {
+            // Safety: `ptr` is valid for `n` `T`s. If `T::default` panics, the previous values are leaked.
+            unsafe { ptr.as_ptr().add(i).write(T::default()) };
+        }
+        // Safety: the `n` values were just initialized, and the memory is not used by anything else
+        unsafe { slice::from_raw_parts_mut(ptr.as_ptr(), n) }
+    }
+
+    /// Allocates a slice holding the values of `iter`
+    ///
+    /// If the iterator yields less values than its `len`, the slice is shorter, and the extra space stays unused.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_from_iter<T, I>(&self, iter: I) -> &mut [T]
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let iter = iter.into_iter();
+        let capacity = iter.len();
+        // Reserved before iterating, so that the iterator may use the arena as well
+        let ptr = self.alloc_uninit::<T>(capacity);
+        let mut len = 0;
+        for value in iter.take(capacity) {
+            // Safety: `len < capacity`, and `ptr` is valid for `capacity` `T`s
+            unsafe { ptr.as_ptr().add(len).write(value) };
+            len += 1;
+        }
+        // Safety: the `len` first values were just initialized, and the memory is not used by anything else
+        unsafe { slice::from_raw_parts_mut(ptr.as_ptr(), len) }
+    }
+
+    /// Number of bytes available in the arena before it needs another chunk
+    pub fn remaining_bytes(&self) -> usize {
+        self.chunk.get().1
+    }
+
+    /// Returns memory that is aligned and valid for `n` `T`s for the lifetime of the arena
+    fn alloc_uninit<T>(&self, n: usize) -> NonNull<T> {
+        let size = mem::size_of::<T>()
+            .checked_mul(n)
+            .expect("capacity overflow");
+        let align = mem::align_of::<T>();
+        if size == 0 {
+            return NonNull::dangling();
+        }
+        let (ptr, len) = self.chunk.get();
+        let padding = ptr.as_ptr().align_offset(align);
+        let (ptr, len) = match padding.checked_add(size) {
+            Some(needed) if needed <= len => (ptr.as_ptr().wrapping_add(padding), len - needed),
+            _ => self.alloc_chunk(size, align),
+        };
+        // Safety: `ptr + size` is at most the end of the chunk, which is not null
+        self.chunk
+            .set((unsafe { NonNull::new_unchecked(ptr.add(size)) }, len));
+        // Safety: `ptr` is in a chunk, so it is not null
+        unsafe { NonNull::new_unchecked(ptr as *mut T) }
+    }
+
+    /// Allocates a new chunk, with room for at least `size` bytes at alignment `align`, and returns the aligned
+    /// start of those bytes and the number of bytes after them
+    fn alloc_chunk(&self, size: usize, align: usize) -> (*mut u8, usize) {
+        let mut overflow = self.overflow.borrow_mut();
+        let previous = overflow
+            .last()
+            .map_or(self.storage.capacity(), Vec::capacity);
+        let needed = size.checked_add(align - 1).expect("capacity overflow");
+        let mut chunk = Vec::<u8>::with_capacity(needed.max(previous.saturating_mul(2)));
+        let padding = chunk.as_mut_ptr().align_offset(align);
+        let remaining = chunk.capacity() - padding - size;
+        let ptr = chunk.as_mut_ptr().wrapping_add(padding);
+        // Moving the `Vec` doesn't move its allocation
+        overflow.push(chunk);
+        (ptr, remaining)
+    }
+}
+
+impl Drop for ScratchArena<'_> {
+    fn drop(&mut self) {
+        let overflow = mem::take(self.overflow.get_mut());
+        if !overflow.is_empty() {
+            let total = self.storage.capacity() + overflow.iter().map(Vec::capacity).sum::<usize>();
+            drop(overflow);
+            // The storage is empty, so this makes its capacity at least `total`
+            self.storage.reserve_exact(total);
+        }
+    }
+}
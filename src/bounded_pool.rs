@@ -0,0 +1,326 @@
+use crate::{recycle, shared::lock, LayoutCheck, ReusableContainer, VecStorageForReuse};
+
+use std::{
+    future::Future,
+    marker::PhantomData,
+    mem,
+    ops::{Deref, DerefMut, Drop},
+    pin::Pin,
+    sync::{Condvar, Mutex, PoisonError},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+/// Holds a fixed number of allocations. Acquiring one waits until one is available instead of allocating.
+///
+/// This puts a hard limit on the number of `Vec`s in use at the same time, and provides backpressure to the
+/// users of the pool.
+/// ```
+/// # use vec_storage_reuse::BoundedVecStoragePool;
+/// let pool: BoundedVecStoragePool<u32> = BoundedVecStoragePool::with_capacity(2, 100);
+///
+/// let first = pool.acquire::<f32>();
+/// let second = pool.acquire::<i32>();
+/// assert!(pool.try_acquire::<u32>().is_none());
+///
+/// drop(first);
+/// assert_eq!(pool.try_acquire::<u32>().unwrap().capacity(), 100);
+/// ```
+pub struct BoundedVecStoragePool<S> {
+    state: Mutex<State<S>>,
+    available: Condvar,
+}
+
+struct State<S> {
+    free: Vec<VecStorageForReuse<S>>,
+    /// Wakers of the pending `Acquire` futures, by id
+    wakers: Vec<(u64, Waker)>,
+    next_waker_id: u64,
+}
+
+impl<S> State<S> {
+    /// Registers the waker of a pending `Acquire`, replacing the one it previously registered if it's still there
+    fn register_waker(&mut self, waker_id: Option<u64>, waker: &Waker) -> u64 {
+        if let Some(waker_id) = waker_id {
+            if let Some((_, registered)) = self.wakers.iter_mut().find(|(id, _)| *id == waker_id) {
+                registered.clone_from(waker);
+                return waker_id;
+            }
+        }
+        let waker_id = self.next_waker_id;
+        self.next_waker_id += 1;
+        self.wakers.push((waker_id, waker.clone()));
+        waker_id
+    }
+}
+
+impl<S> BoundedVecStoragePool<S> {
+    /// Creates a pool of `n_buffers` allocations, which are initially empty
+    pub fn new(n_buffers: usize) -> Self {
+        Self::with_capacity(n_buffers, 0)
+    }
+
+    /// Creates a pool of `n_buffers` allocations of `capacity` elements each
+    pub fn with_capacity(n_buffers: usize, capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(State {
+                free: (0..n_buffers)
+                    .map(|_| VecStorageForReuse::with_capacity(capacity))
+                    .collect(),
+                wakers: Vec::new(),
+                next_waker_id: 0,
+            }),
+            available: Condvar::new(),
+        }
+    }
+
+    /// Takes an allocation from the pool to provide a `BoundedVecStorageReuse: DerefMut<Target = Vec<T>>`,
+    /// blocking the current thread until one is available
+    ///
+    /// `T` must have the same alignment as `S`, which is checked at compile time.
+    ///
+    /// A zero-sized `T` doesn't use the allocation, which is put back untouched:
+    /// ```
+    /// # use vec_storage_reuse::BoundedVecStoragePool;
+    /// let pool: BoundedVecStoragePool<u32> = BoundedVecStoragePool::with_capacity(1, 100);
+    /// pool.acquire::<[u32; 0]>().push([]);
+    /// assert_eq!(pool.acquire::<u32>().capacity(), 100);
+    /// ```
+    pub fn acquire<T>(&self) -> BoundedVecStorageReuse<'_, T, S> {
+        self.acquire_as()
+    }
+
+    /// Same as `acquire`, for any `ReusableContainer` `C`
+    pub fn acquire_as<C: ReusableContainer>(&self) -> BoundedStorageReuse<'_, C, S> {
+        let mut state = lock(&self.state);
+        loop {
+            if let Some(storage) = state.free.pop() {
+                return self.guard(storage);
+            }
+            state = self
+                .available
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Same as `acquire`, but returns `None` instead of blocking if no allocation is available
+    pub fn try_acquire<T>(&self) -> Option<BoundedVecStorageReuse<'_, T, S>> {
+        self.try_acquire_as()
+    }
+
+    /// Same as `try_acquire`, for any `ReusableContainer` `C`
+    /// ```
+    /// # use vec_storage_reuse::BoundedVecStoragePool;
+    /// use std::collections::VecDeque;
+    ///
+    /// let pool: BoundedVecStoragePool<u32> = BoundedVecStoragePool::with_capacity(1, 100);
+    /// let mut queue = pool.try_acquire_as::<VecDeque<u32>>().unwrap();
+    /// queue.push_front(1);
+    /// assert!(queue.capacity() >= 100);
+    /// ```
+    pub fn try_acquire_as<C: ReusableContainer>(&self) -> Option<BoundedStorageReuse<'_, C, S>> {
+        let storage = lock(&self.state).free.pop()?;
+        Some(self.guard(storage))
+    }
+
+    /// Same as `acquire`, but returns `None` if no allocation became available before `timeout`
+    /// ```
+    /// # use vec_storage_reuse::BoundedVecStoragePool;
+    /// use std::time::Duration;
+    ///
+    /// let pool: BoundedVecStoragePool<u32> = BoundedVecStoragePool::new(1);
+    /// let first = pool.acquire_timeout::<u32>(Duration::MAX).unwrap();
+    /// assert!(pool.acquire_timeout::<u32>(Duration::from_millis(1)).is_none());
+    /// ```
+    pub fn acquire_timeout<T>(
+        &self,
+        timeout: Duration,
+    ) -> Option<BoundedVecStorageReuse<'_, T, S>> {
+        self.acquire_timeout_as(timeout)
+    }
+
+    /// Same as `acquire_timeout`, for any `ReusableContainer` `C`
+    pub fn acquire_timeout_as<C: ReusableContainer>(
+        &self,
+        timeout: Duration,
+    ) -> Option<BoundedStorageReuse<'_, C, S>> {
+        let deadline = match Instant::now().checked_add(timeout) {
+            Some(deadline) => deadline,
+            // Too far away to ever be reached
+            None => return Some(self.acquire_as()),
+        };
+        let mut state = lock(&self.state);
+        loop {
+            if let Some(storage) = state.free.pop() {
+                return Some(self.guard(storage));
+            }
+            let remaining = deadline.checked_duration_since(Instant::now())?;
+            state = self
+                .available
+                .wait_timeout(state, remaining)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    /// Same as `acquire`, but returns a future that waits for an allocation to be available instead of blocking
+    /// the current thread
+    /// ```
+    /// # use vec_storage_reuse::BoundedVecStoragePool;
+    /// # use std::{future::Future, sync::Arc, task::{Context, Poll, Wake, Waker}};
+    /// # struct Noop;
+    /// # impl Wake for Noop {
+    /// #     fn wake(self: Arc<Self>) {}
+    /// # }
+    /// # let waker = Waker::from(Arc::new(Noop));
+    /// let pool: BoundedVecStoragePool<u32> = BoundedVecStoragePool::new(1);
+    /// let mut context = Context::from_waker(&waker);
+    ///
+    /// let first = pool.acquire::<u32>();
+    /// let mut second = Box::pin(pool.acquire_async::<u32>());
+    /// assert!(second.as_mut().poll(&mut context).is_pending());
+    ///
+    /// drop(first);
+    /// assert!(matches!(second.as_mut().poll(&mut context), Poll::Ready(_)));
+    /// ```
+    pub fn acquire_async<T>(&self) -> Acquire<'_, T, S> {
+        self.acquire_async_as()
+    }
+
+    /// Same as `acquire_async`, for any `ReusableContainer` `C`
+    /// ```
+    /// # use vec_storage_reuse::BoundedVecStoragePool;
+    /// # use std::{collections::VecDeque, future::Future, sync::Arc, task::{Context, Poll, Wake, Waker}};
+    /// # struct Noop;
+    /// # impl Wake for Noop {
+    /// #     fn wake(self: Arc<Self>) {}
+    /// # }
+    /// # let waker = Waker::from(Arc::new(Noop));
+    /// let pool: BoundedVecStoragePool<u32> = BoundedVecStoragePool::with_capacity(1, 100);
+    /// let mut context = Context::from_waker(&waker);
+    ///
+    /// let mut acquire = Box::pin(pool.acquire_async_as::<VecDeque<u32>>());
+    /// let queue = match acquire.as_mut().poll(&mut context) {
+    ///     Poll::Ready(queue) => queue,
+    ///     Poll::Pending => unreachable!(),
+    /// };
+    /// assert!(queue.capacity() >= 100);
+    /// ```
+    pub fn acquire_async_as<C: ReusableContainer>(&self) -> AcquireAs<'_, C, S> {
+        AcquireAs {
+            pool: self,
+            waker_id: None,
+            _target: PhantomData,
+        }
+    }
+
+    /// Number of allocations currently available in the pool
+    pub fn available(&self) -> usize {
+        lock(&self.state).free.len()
+    }
+
+    fn guard<C: ReusableContainer>(
+        &self,
+        mut storage: VecStorageForReuse<S>,
+    ) -> BoundedStorageReuse<'_, C, S> {
+        let () = LayoutCheck::<S, C::Item>::SAME_ALIGN;
+        let inner = if mem::size_of::<C::Item>() == 0 {
+            // Keep the allocation in the storage, there's no use for it
+            Vec::new()
+        } else {
+            recycle::recycle(mem::take(&mut storage.inner))
+        };
+        BoundedStorageReuse {
+            pool: self,
+            storage,
+            inner: C::from_empty_vec(inner),
+        }
+    }
+
+    fn put_back(&self, storage: VecStorageForReuse<S>) {
+        let wakers = {
+            let mut state = lock(&self.state);
+            state.free.push(storage);
+            mem::take(&mut state.wakers)
+        };
+        self.available.notify_one();
+        // All the pending futures are woken, as we can't know whether the one we'd pick is still going to be polled
+        for (_, waker) in wakers {
+            waker.wake();
+        }
+    }
+}
+
+/// Future returned by `BoundedVecStoragePool::acquire_async_as`
+pub struct AcquireAs<'p, C, S> {
+    pool: &'p BoundedVecStoragePool<S>,
+    waker_id: Option<u64>,
+    _target: PhantomData<fn() -> C>,
+}
+
+/// Future returned by `BoundedVecStoragePool::acquire_async`
+pub type Acquire<'p, T, S> = AcquireAs<'p, Vec<T>, S>;
+
+impl<'p, C: ReusableContainer, S> Future for AcquireAs<'p, C, S> {
+    type Output = BoundedStorageReuse<'p, C, S>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let pool = self.pool;
+        let mut state = lock(&pool.state);
+        if let Some(storage) = state.free.pop() {
+            drop(state);
+            self.waker_id = None;
+            return Poll::Ready(pool.guard(storage));
+        }
+        let waker_id = state.register_waker(self.waker_id, cx.waker());
+        self.waker_id = Some(waker_id);
+        Poll::Pending
+    }
+}
+
+impl<C, S> Drop for AcquireAs<'_, C, S> {
+    fn drop(&mut self) {
+        if let Some(waker_id) = self.waker_id {
+            lock(&self.pool.state)
+                .wakers
+                .retain(|(id, _)| *id != waker_id);
+        }
+    }
+}
+
+/// Implements `DerefMut<Target = C>`, and puts the allocation back in the source `BoundedVecStoragePool<S>` once
+/// dropped, waking up a waiting user of the pool
+pub struct BoundedStorageReuse<'p, C: ReusableContainer, S> {
+    pool: &'p BoundedVecStoragePool<S>,
+    /// Only holds the allocation if `C::Item` is zero-sized
+    storage: VecStorageForReuse<S>,
+    inner: C,
+}
+
+/// Implements `DerefMut<Target = Vec<T>>`, and puts the allocation back in the source `BoundedVecStoragePool<S>`
+/// once dropped, waking up a waiting user of the pool
+pub type BoundedVecStorageReuse<'p, T, S> = BoundedStorageReuse<'p, Vec<T>, S>;
+
+impl<C: ReusableContainer, S> Drop for BoundedStorageReuse<'_, C, S> {
+    fn drop(&mut self) {
+        let inner = mem::replace(&mut self.inner, C::from_empty_vec(Vec::new())).into_empty_vec();
+        let mut storage = mem::take(&mut self.storage);
+        if mem::size_of::<C::Item>() != 0 {
+            storage.inner = recycle::recycle(inner);
+        }
+        self.pool.put_back(storage);
+    }
+}
+
+impl<C: ReusableContainer, S> Deref for BoundedStorageReuse<'_, C, S> {
+    type Target = C;
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+impl<C: ReusableContainer, S> DerefMut for BoundedStorageReuse<'_, C, S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
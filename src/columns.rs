@@ -0,0 +1,462 @@
+use crate::{recycle, shared::lock, ErasedVecStorage};
+
+use std::{
+    alloc::{self, Layout},
+    cmp, fmt,
+    marker::PhantomData,
+    mem,
+    ops::{Deref, DerefMut, Drop},
+    ptr::{self, NonNull},
+    sync::{Mutex, PoisonError},
+};
+
+impl ErasedVecStorage {
+    /// Partitions the stored allocation into one `Column` per element type of the tuple `C`, each with room for
+    /// `capacity` elements
+    ///
+    /// This is a struct-of-arrays version of `reuse_allocation`: all the columns share a single allocation, which
+    /// is only replaced if it is too small or not aligned enough for them, or if a column spilled to a larger
+    /// allocation the previous time.
+    /// ```
+    /// # use vec_storage_reuse::ErasedVecStorage;
+    /// let mut row_group_storage = ErasedVecStorage::new();
+    ///
+    /// for row_group in ["a 1 y, b 2 n", "c 3 n"] {
+    ///     let row_group = row_group.to_owned(); // only lives this scope
+    ///     let (mut names, mut ids, mut flags) =
+    ///         row_group_storage.reuse_columns::<(&str, u32, bool)>(16);
+    ///     for row in row_group.split(", ") {
+    ///         let mut fields = row.split(' ');
+    ///         names.push(fields.next().unwrap());
+    ///         ids.push(fields.next().unwrap().parse().unwrap());
+    ///         flags.push(fields.next() == Some("y"));
+    ///     }
+    ///     assert_eq!(names.len(), ids.len());
+    ///     assert!(flags.capacity() >= 16);
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if the size of the columns overflows `isize`.
+    pub fn reuse_columns<C: ColumnTypes>(&mut self, capacity: usize) -> C::Columns<'_> {
+        self.keep_spilled();
+        let layout = C::layout(capacity);
+        let base = match self.allocation {
+            _ if layout.size() == 0 => NonNull::dangling(),
+            Some((ptr, current))
+                if current.size() >= layout.size() && current.align() >= layout.align() =>
+            {
+                ptr
+            }
+            current => {
+                let (mut size, mut align) = (layout.size(), layout.align());
+                if let Some((ptr, current)) = current {
+                    size = cmp::max(size, current.size());
+                    align = cmp::max(align, current.align());
+                    self.allocation = None;
+                    // Safety: the allocation is owned by the storage, which we just emptied
+                    unsafe { alloc::dealloc(ptr.as_ptr(), current) }
+                }
+                let layout = Layout::from_size_align(size, align).expect("capacity overflow");
+                // Safety: `layout` has a non-zero size
+                let ptr = NonNull::new(unsafe { alloc::alloc(layout) })
+                    .unwrap_or_else(|| alloc::handle_alloc_error(layout));
+                self.allocation = Some((ptr, layout));
+                ptr
+            }
+        };
+        // Safety: `base` is valid for `C::layout(capacity)`, and stays borrowed by the columns
+        unsafe { C::columns(self, base, capacity) }
+    }
+
+    /// Replaces the stored allocation by the one a spilled `Column` left, if that one is larger
+    pub(crate) fn keep_spilled(&mut self) {
+        if let Some((ptr, layout)) = self.spilled.take() {
+            let smallest = match self.allocation {
+                Some((_, current)) if current.size() >= layout.size() => Some((ptr, layout)),
+                _ => self.allocation.replace((ptr, layout)),
+            };
+            if let Some((ptr, layout)) = smallest {
+                // Safety: the allocation is owned by the storage, and no longer stored
+                unsafe { alloc::dealloc(ptr.as_ptr(), layout) }
+            }
+        }
+    }
+}
+
+/// Tuple of element types that `ErasedVecStorage::reuse_columns` lays out next to each other in one allocation
+///
+/// Implemented for tuples of up to 6 types.
+pub trait ColumnTypes {
+    /// Tuple of the `Column`s of each element type
+    type Columns<'s>;
+
+    /// Layout of the allocation holding all the columns
+    #[doc(hidden)]
+    fn layout(capacity: usize) -> Layout;
+
+    /// # Safety
+    /// `base` must be valid for reads and writes of `Self::layout(capacity)` for `'s`
+    #[doc(hidden)]
+    unsafe fn columns<'s>(
+        storage: &'s ErasedVecStorage,
+        base: NonNull<u8>,
+        capacity: usize,
+    ) -> Self::Columns<'s>;
+}
+
+/// Appends an array of `capacity` `T`s to `layout`, returning the new layout and the offset of the array
+fn extend<T>(layout: Layout, capacity: usize) -> (Layout, usize) {
+    Layout::array::<T>(capacity)
+        .and_then(|array| layout.extend(array))
+        .expect("capacity overflow")
+}
+
+macro_rules! tuple_columns {
+    ($($t: ident)+) => {
+        impl<$($t),+> ColumnTypes for ($($t,)+) {
+            type Columns<'s> = ($(Column<'s, $t>,)+);
+
+            fn layout(capacity: usize) -> Layout {
+                let layout = Layout::new::<()>();
+                $(let (layout, _) = extend::<$t>(layout, capacity);)+
+                layout
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn columns<'s>(
+                storage: &'s ErasedVecStorage,
+                base: NonNull<u8>,
+                capacity: usize,
+            ) -> Self::Columns<'s> {
+                let layout = Layout::new::<()>();
+                $(let (layout, $t) = extend::<$t>(layout, capacity);)+
+                let _ = layout;
+                ($(Column::from_raw_parts(storage, base, $t, capacity),)+)
+            }
+        }
+    };
+}
+tuple_columns!(A);
+tuple_columns!(A B);
+tuple_columns!(A B C);
+tuple_columns!(A B C D);
+tuple_columns!(A B C D E);
+tuple_columns!(A B C D E F);
+
+/// Vector of `T`s in a part of the allocation of an `ErasedVecStorage`, handed out by `reuse_columns`
+///
+/// A column that outgrows its part of the allocation moves its values to an allocation of its own. The values are
+/// dropped with the column, and if the column spilled, its allocation is kept by the storage if it is larger than
+/// the stored one, to be reused by the next call to `reuse_columns`:
+/// ```
+/// # use vec_storage_reuse::ErasedVecStorage;
+/// let mut storage = ErasedVecStorage::new();
+///
+/// let (mut ids, mut weights) = storage.reuse_columns::<(u64, f64)>(2);
+/// ids.extend(0..10);
+/// weights.push(1.0);
+/// assert!(ids.is_spilled() && !weights.is_spilled());
+/// let spilled_capacity = ids.capacity();
+/// drop((ids, weights));
+///
+/// let (ids,) = storage.reuse_columns::<(u64,)>(1);
+/// assert_eq!(ids.capacity(), 1);
+/// drop(ids);
+/// assert_eq!(storage.capacity_bytes(), spilled_capacity * 8);
+/// ```
+pub struct Column<'s, T> {
+    buffer: ColumnBuffer<'s, T>,
+    storage: &'s ErasedVecStorage,
+}
+
+impl<'s, T> Column<'s, T> {
+    /// # Safety
+    /// `base + offset` must be aligned for `T` and valid for `capacity` `T`s for `'s`
+    unsafe fn from_raw_parts(
+        storage: &'s ErasedVecStorage,
+        base: NonNull<u8>,
+        offset: usize,
+        capacity: usize,
+    ) -> Self {
+        Self {
+            buffer: ColumnBuffer::from_raw_parts(base, offset, capacity),
+            storage,
+        }
+    }
+
+    /// Whether the values were moved to an allocation of their own
+    pub fn is_spilled(&self) -> bool {
+        self.buffer.is_spilled()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    pub fn push(&mut self, value: T) {
+        self.buffer.push(value);
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.buffer.pop()
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.buffer.truncate(len);
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+}
+
+impl<T> Drop for Column<'_, T> {
+    fn drop(&mut self) {
+        if let Some(vec) = self.buffer.take_spilled() {
+            self.storage.spilled.keep(recycle::into_allocation(vec));
+        }
+    }
+}
+
+impl<T> Extend<T> for Column<'_, T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+impl<T> Deref for Column<'_, T> {
+    type Target = [T];
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+impl<T> DerefMut for Column<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buffer
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Column<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+/// Largest allocation left by the `Column`s that spilled, which can only replace the allocation of the storage once
+/// no `Column` points to it anymore
+#[derive(Default)]
+pub(crate) struct SpilledAllocation(Mutex<Option<(NonNull<u8>, Layout)>>);
+
+impl SpilledAllocation {
+    /// Keeps the largest of `allocation` and the one already kept, and frees the other one
+    fn keep(&self, allocation: Option<(NonNull<u8>, Layout)>) {
+        if let Some((ptr, layout)) = allocation {
+            let mut kept = lock(&self.0);
+            let smallest = match *kept {
+                Some((_, current)) if current.size() >= layout.size() => Some((ptr, layout)),
+                _ => kept.replace((ptr, layout)),
+            };
+            if let Some((ptr, layout)) = smallest {
+                // Safety: the allocation is owned by the storage, and no longer kept
+                unsafe { alloc::dealloc(ptr.as_ptr(), layout) }
+            }
+        }
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        lock(&self.0).map_or(0, |(_, layout)| layout.size())
+    }
+
+    pub(crate) fn take(&mut self) -> Option<(NonNull<u8>, Layout)> {
+        self.0
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+}
+
+impl Drop for SpilledAllocation {
+    fn drop(&mut self) {
+        if let Some((ptr, layout)) = self.take() {
+            // Safety: the allocation is owned by the storage
+            unsafe { alloc::dealloc(ptr.as_ptr(), layout) }
+        }
+    }
+}
+
+/// Values of a `Column` or of a `SplitVec`: in a part of a borrowed allocation until they need more room, and then
+/// in an allocation of their own
+pub(crate) enum ColumnBuffer<'s, T> {
+    Shared(FixedColumn<'s, T>),
+    Spilled(Vec<T>),
+}
+
+impl<'s, T> ColumnBuffer<'s, T> {
+    /// # Safety
+    /// `base + offset` must be aligned for `T` and valid for `capacity` `T`s for `'s`
+    pub(crate) unsafe fn from_raw_parts(base: NonNull<u8>, offset: usize, capacity: usize) -> Self {
+        Self::Shared(FixedColumn::from_raw_parts(base, offset, capacity))
+    }
+
+    pub(crate) fn is_spilled(&self) -> bool {
+        matches!(self, Self::Spilled(_))
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        match self {
+            Self::Shared(column) => column.capacity,
+            Self::Spilled(vec) => vec.capacity(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, value: T) {
+        match self {
+            Self::Shared(column) => {
+                if let Err(value) = column.try_push(value) {
+                    let mut vec = Vec::with_capacity((column.capacity * 2).max(4));
+                    column.move_into(&mut vec);
+                    vec.push(value);
+                    *self = Self::Spilled(vec);
+                }
+            }
+            Self::Spilled(vec) => vec.push(value),
+        }
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<T> {
+        match self {
+            Self::Shared(column) => column.pop(),
+            Self::Spilled(vec) => vec.pop(),
+        }
+    }
+
+    pub(crate) fn truncate(&mut self, len: usize) {
+        match self {
+            Self::Shared(column) => column.truncate(len),
+            Self::Spilled(vec) => vec.truncate(len),
+        }
+    }
+
+    /// Drops the values, and returns the own allocation if the values were spilled
+    pub(crate) fn take_spilled(&mut self) -> Option<Vec<T>> {
+        match self {
+            Self::Shared(column) => {
+                column.truncate(0);
+                None
+            }
+            Self::Spilled(vec) => {
+                vec.clear();
+                Some(mem::take(vec))
+            }
+        }
+    }
+}
+
+impl<T> Deref for ColumnBuffer<'_, T> {
+    type Target = [T];
+    fn deref(&self) -> &Self::Target {
+        match self {
+            // Safety: the first `len` values are initialized
+            Self::Shared(column) => unsafe {
+                std::slice::from_raw_parts(column.ptr.as_ptr(), column.len)
+            },
+            Self::Spilled(vec) => vec,
+        }
+    }
+}
+impl<T> DerefMut for ColumnBuffer<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            // Safety: the first `len` values are initialized
+            Self::Shared(column) => unsafe {
+                std::slice::from_raw_parts_mut(column.ptr.as_ptr(), column.len)
+            },
+            Self::Spilled(vec) => vec,
+        }
+    }
+}
+
+/// Fixed-capacity vector of `T`s in a part of a borrowed allocation
+pub(crate) struct FixedColumn<'s, T> {
+    ptr: NonNull<T>,
+    len: usize,
+    capacity: usize,
+    _marker: PhantomData<(&'s mut [u8], T)>,
+}
+
+impl<T> FixedColumn<'_, T> {
+    /// # Safety
+    /// `base + offset` must be aligned for `T` and valid for `capacity` `T`s for `'s`
+    unsafe fn from_raw_parts(base: NonNull<u8>, offset: usize, capacity: usize) -> Self {
+        let ptr = match mem::size_of::<T>() * capacity {
+            0 => NonNull::dangling(),
+            _ => NonNull::new_unchecked(base.as_ptr().add(offset) as *mut T),
+        };
+        Self {
+            ptr,
+            len: 0,
+            capacity,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Appends `value`, or gives it back if the column is full
+    fn try_push(&mut self, value: T) -> Result<(), T> {
+        if self.len == self.capacity {
+            return Err(value);
+        }
+        // Safety: `len < capacity`, so the slot is in the column and not initialized
+        unsafe { self.ptr.as_ptr().add(self.len).write(value) };
+        self.len += 1;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        // Safety: the slot was initialized, and is no longer part of the column
+        Some(unsafe { self.ptr.as_ptr().add(self.len).read() })
+    }
+
+    fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+        let tail = ptr::slice_from_raw_parts_mut(
+            // Safety: `len < self.len`, so this is in the column
+            unsafe { self.ptr.as_ptr().add(len) },
+            self.len - len,
+        );
+        // Set the length first, so that a panicking `drop` can't cause a double drop
+        self.len = len;
+        // Safety: these values were initialized, and are no longer part of the column
+        unsafe { ptr::drop_in_place(tail) };
+    }
+
+    /// Moves all the values to the end of `vec`
+    fn move_into(&mut self, vec: &mut Vec<T>) {
+        vec.reserve(self.len);
+        // Safety: the values are moved to the spare capacity of `vec`, and are no longer part of the column
+        unsafe {
+            ptr::copy_nonoverlapping(self.ptr.as_ptr(), vec.as_mut_ptr().add(vec.len()), self.len);
+            vec.set_len(vec.len() + self.len);
+        }
+        self.len = 0;
+    }
+}
+
+impl<T> Drop for FixedColumn<'_, T> {
+    fn drop(&mut self) {
+        self.truncate(0);
+    }
+}
+
+/// Safety: the column owns its values, and borrows its memory exclusively
+unsafe impl<T: Send> Send for FixedColumn<'_, T> {}
+/// Safety: the column owns its values, and borrows its memory exclusively
+unsafe impl<T: Sync> Sync for FixedColumn<'_, T> {}
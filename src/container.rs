@@ -0,0 +1,96 @@
+use std::collections::{BinaryHeap, VecDeque};
+
+/// A container backed by a `Vec`'s allocation, which can then be handed out by the storages of this crate
+///
+/// The storages take an empty allocation, reinterpret it as a `Vec<Self::Item>`, turn it into the container with
+/// `from_empty_vec`, and turn it back into a `Vec` with `into_empty_vec` to give it back.
+///
+/// This allows third-party containers to reuse the allocation of a storage:
+/// ```
+/// # use vec_storage_reuse::{ReusableContainer, VecStorageForReuse};
+/// struct SortedVec<T>(Vec<T>);
+///
+/// impl<T: Ord> SortedVec<T> {
+///     fn insert(&mut self, value: T) {
+///         let index = self.0.binary_search(&value).unwrap_or_else(|index| index);
+///         self.0.insert(index, value);
+///     }
+/// }
+///
+/// impl<T> ReusableContainer for SortedVec<T> {
+///     type Item = T;
+///     fn from_empty_vec(vec: Vec<T>) -> Self {
+///         SortedVec(vec)
+///     }
+///     fn into_empty_vec(self) -> Vec<T> {
+///         let mut vec = self.0;
+///         vec.clear();
+///         vec
+///     }
+/// }
+///
+/// let mut storage: VecStorageForReuse<u32> = VecStorageForReuse::new();
+/// let mut sorted = storage.reuse_as::<SortedVec<u32>>();
+/// sorted.insert(3);
+/// sorted.insert(1);
+/// assert_eq!(sorted.0, [1, 3]);
+/// ```
+pub trait ReusableContainer: Sized {
+    /// Element type of the `Vec` backing the container
+    type Item;
+
+    /// Builds an empty container using the allocation of `vec`, which is empty
+    fn from_empty_vec(vec: Vec<Self::Item>) -> Self;
+
+    /// Clears the container and gives its allocation back
+    ///
+    /// Any element left in the returned `Vec` is dropped.
+    fn into_empty_vec(self) -> Vec<Self::Item>;
+}
+
+impl<T> ReusableContainer for Vec<T> {
+    type Item = T;
+    fn from_empty_vec(vec: Vec<T>) -> Self {
+        vec
+    }
+    fn into_empty_vec(mut self) -> Vec<T> {
+        self.clear();
+        self
+    }
+}
+
+impl<T> ReusableContainer for VecDeque<T> {
+    type Item = T;
+    fn from_empty_vec(vec: Vec<T>) -> Self {
+        VecDeque::from(vec)
+    }
+    fn into_empty_vec(mut self) -> Vec<T> {
+        // Once empty, the conversion doesn't need to move any element
+        self.clear();
+        Vec::from(self)
+    }
+}
+
+impl<T: Ord> ReusableContainer for BinaryHeap<T> {
+    type Item = T;
+    fn from_empty_vec(vec: Vec<T>) -> Self {
+        BinaryHeap::from(vec)
+    }
+    fn into_empty_vec(mut self) -> Vec<T> {
+        self.clear();
+        self.into_vec()
+    }
+}
+
+impl ReusableContainer for String {
+    type Item = u8;
+    fn from_empty_vec(mut vec: Vec<u8>) -> Self {
+        vec.clear();
+        String::from_utf8(vec).expect("Empty Vec should be valid UTF-8")
+    }
+    fn into_empty_vec(self) -> Vec<u8> {
+        let mut vec = self.into_bytes();
+        vec.clear();
+        vec
+    }
+}
@@ -0,0 +1,31 @@
+use crate::{StorageReuse, VecStorageForReuse};
+
+use std::collections::VecDeque;
+
+/// Implements `DerefMut<Target = VecDeque<T>>`, and puts the allocation back in place
+/// in the source `Vec<S>` once dropped
+pub type VecDequeStorageReuse<'a, T, S> = StorageReuse<'a, VecDeque<T>, S>;
+
+impl<S> VecStorageForReuse<S> {
+    /// Uses the inner `Vec<S>` storage to provide a `VecDequeStorageReuse: DerefMut<Target = VecDeque<T>>`
+    ///
+    /// This avoids reallocating a new `VecDeque<T>`: converting between `Vec` and `VecDeque` never reallocates.
+    /// `T` must have the same alignment as `S`, which is checked at compile time.
+    /// ```
+    /// # use vec_storage_reuse::VecStorageForReuse;
+    /// let mut storage: VecStorageForReuse<&'static u32> = VecStorageForReuse::with_capacity(10);
+    /// let nodes = vec![1, 2, 3];
+    ///
+    /// let mut queue = storage.reuse_as_deque::<&u32>();
+    /// assert!(queue.capacity() >= 10);
+    /// queue.extend(&nodes);
+    /// while let Some(node) = queue.pop_front() {
+    ///     if *node < 3 {
+    ///         queue.push_back(&nodes[*node as usize]);
+    ///     }
+    /// }
+    /// ```
+    pub fn reuse_as_deque<T>(&mut self) -> VecDequeStorageReuse<'_, T, S> {
+        self.reuse_as()
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{columns::SpilledAllocation, recycle};
+
+use std::{
+    alloc::{self, Layout},
+    mem,
+    ops::{Deref, DerefMut, Drop},
+    ptr::NonNull,
+};
+
+/// Stores an allocation without any element type, so that it can be reused as a `Vec` of any type.
+///
+/// This is useful when several `Vec`s of different element types are needed in turn, but only one
+/// field should hold their allocation:
+/// ```
+/// # use vec_storage_reuse::ErasedVecStorage;
+/// let mut storage = ErasedVecStorage::new();
+/// storage.reuse_allocation::<u64>().extend(0..100);
+///
+/// // Same alignment as `u64`: the allocation is reused
+/// assert_eq!(storage.reuse_allocation::<(u32, u32)>().capacity(), 100);
+///
+/// // Different alignment: the allocation is replaced by one of the same size
+/// assert_eq!(storage.reuse_allocation::<u32>().capacity(), 200);
+/// ```
+pub struct ErasedVecStorage {
+    /// `None` when there is no allocation
+    pub(crate) allocation: Option<(NonNull<u8>, Layout)>,
+    /// Kept aside until `allocation` is no longer used by the `Column`s
+    pub(crate) spilled: SpilledAllocation,
+}
+
+impl ErasedVecStorage {
+    pub fn new() -> Self {
+        Self {
+            allocation: None,
+            spilled: SpilledAllocation::default(),
+        }
+    }
+
+    pub fn from_vec<S>(vec_to_use_as_storage: Vec<S>) -> Self {
+        Self {
+            allocation: recycle::into_allocation(vec_to_use_as_storage),
+            spilled: SpilledAllocation::default(),
+        }
+    }
+
+    /// Size in bytes of the stored allocation
+    pub fn capacity_bytes(&self) -> usize {
+        let stored = self.allocation.map_or(0, |(_, layout)| layout.size());
+        stored.max(self.spilled.size())
+    }
+
+    /// Uses the stored allocation to provide an `ErasedVecStorageReuse: DerefMut<Target = Vec<T>>`
+    ///
+    /// The allocation is reused if it has the alignment of `T`. Otherwise it is replaced by an allocation with the
+    /// alignment of `T` and the same size. Either way, the capacity is converted so that the allocation keeps
+    /// (at most) the same size in bytes.
+    pub fn reuse_allocation<T>(&mut self) -> ErasedVecStorageReuse<'_, T> {
+        self.keep_spilled();
+        let inner = match self.allocation {
+            Some(_) if mem::size_of::<T>() == 0 => Vec::new(),
+            // Safety: the allocation is owned by the storage, which we just emptied
+            Some((ptr, layout)) => unsafe {
+                self.allocation = None;
+                recycle::vec_from_allocation(ptr, layout)
+            },
+            None => Vec::new(),
+        };
+        ErasedVecStorageReuse {
+            storage: self,
+            inner,
+        }
+    }
+
+    pub fn into_vec<S>(mut self) -> Vec<S> {
+        self.keep_spilled();
+        match self.allocation.take() {
+            // Safety: the allocation is owned by the storage, which we just emptied
+            Some((ptr, layout)) => unsafe { recycle::vec_from_allocation(ptr, layout) },
+            None => Vec::new(),
+        }
+    }
+}
+
+impl Drop for ErasedVecStorage {
+    fn drop(&mut self) {
+        if let Some((ptr, layout)) = self.allocation.take() {
+            // Safety: the allocation is owned by the storage
+            unsafe { alloc::dealloc(ptr.as_ptr(), layout) }
+        }
+    }
+}
+
+/// Safety: the storage only holds an allocation, without any values in it
+unsafe impl Send for ErasedVecStorage {}
+/// Safety: the storage only holds an allocation, without any values in it
+unsafe impl Sync for ErasedVecStorage {}
+
+impl Default for ErasedVecStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Implements `DerefMut<Target = Vec<T>>`, and puts the allocation back in place
+/// in the source `ErasedVecStorage` once dropped
+pub struct ErasedVecStorageReuse<'a, T> {
+    storage: &'a mut ErasedVecStorage,
+    inner: Vec<T>,
+}
+
+impl<T> Drop for ErasedVecStorageReuse<'_, T> {
+    fn drop(&mut self) {
+        if mem::size_of::<T>() != 0 {
+            self.storage.allocation = recycle::into_allocation(mem::take(&mut self.inner));
+        }
+    }
+}
+
+impl<T> Deref for ErasedVecStorageReuse<'_, T> {
+    type Target = Vec<T>;
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+impl<T> DerefMut for ErasedVecStorageReuse<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
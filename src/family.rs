@@ -0,0 +1,196 @@
+//! Lifetime families, to describe the element type of a `VecStorageFor` without
+//! having to pick a placeholder lifetime for it.
+//!
+//! A family is a type that, given any lifetime `'a`, provides the type `Of<'a>`.
+//! Families for references, slices and tuples are provided here, and any type can be turned into a
+//! family with the `storage_for!` macro by replacing its lifetime with `'_`.
+
+use crate::{recycle, VecStorageForReuse, VecStorageReuse};
+
+use std::marker::PhantomData;
+
+/// A type constructor over a lifetime: `Of<'a>` is the type for the lifetime `'a`.
+///
+/// All the types of a family must have the same layout, which is always the case since
+/// lifetimes don't change the layout of a type.
+pub trait ReuseFamily {
+    type Of<'a>: 'a;
+}
+
+/// Helper to build a family from any type through a `dyn for<'a> WithLifetime<'a, Of = Type<'a>>`.
+///
+/// This is what `storage_for!` expands to. See `ForLifetime`.
+pub trait WithLifetime<'a> {
+    type Of: 'a;
+}
+
+/// Family of the `WithLifetime::Of` types of a `dyn for<'a> WithLifetime<'a, Of = Type<'a>>`.
+///
+/// This is usually written with the `storage_for!` macro.
+pub struct ForLifetime<T: ?Sized>(PhantomData<T>);
+
+impl<T: ?Sized + for<'a> WithLifetime<'a>> ReuseFamily for ForLifetime<T> {
+    type Of<'a> = <T as WithLifetime<'a>>::Of;
+}
+
+/// Writes the family type of the given type, where the lifetime that changes is written `'_`
+///
+/// ```
+/// # use vec_storage_reuse::{storage_for, VecStorageFor};
+/// struct Object<'a> {
+///     reference: &'a [u8],
+/// }
+///
+/// let mut objects_storage: VecStorageFor<storage_for!(Object<'_>)> = VecStorageFor::new();
+/// let mut pairs_storage: VecStorageFor<storage_for!((&'_ str, Option<&'_ [u8]>))> = VecStorageFor::new();
+/// ```
+#[macro_export]
+macro_rules! storage_for {
+    ($($ty: tt)+) => {
+        $crate::__storage_for_munch!([] [] $($ty)+)
+    };
+}
+
+/// Replaces `'_` by a higher-ranked lifetime in the tokens of `storage_for!`, including in nested groups.
+///
+/// The first bracket is the stack of the groups being explored, each with the tokens already output and the
+/// tokens that follow it. The second bracket holds the tokens already output for the current group.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __storage_for_munch {
+    ([] [$($out: tt)*]) => {
+        $crate::family::ForLifetime<dyn for<'__reuse> $crate::family::WithLifetime<'__reuse, Of = $($out)*>>
+    };
+    ([[($($prev: tt)*) [$($after: tt)*]] $($stack: tt)*] [$($out: tt)*]) => {
+        $crate::__storage_for_munch!([$($stack)*] [$($prev)* ($($out)*)] $($after)*)
+    };
+    ([[[$($prev: tt)*] [$($after: tt)*]] $($stack: tt)*] [$($out: tt)*]) => {
+        $crate::__storage_for_munch!([$($stack)*] [$($prev)* [$($out)*]] $($after)*)
+    };
+    ([$($stack: tt)*] [$($out: tt)*] '_ $($rest: tt)*) => {
+        $crate::__storage_for_munch!([$($stack)*] [$($out)* '__reuse] $($rest)*)
+    };
+    ([$($stack: tt)*] [$($out: tt)*] ($($inner: tt)*) $($rest: tt)*) => {
+        $crate::__storage_for_munch!([[($($out)*) [$($rest)*]] $($stack)*] [] $($inner)*)
+    };
+    ([$($stack: tt)*] [$($out: tt)*] [$($inner: tt)*] $($rest: tt)*) => {
+        $crate::__storage_for_munch!([[[$($out)*] [$($rest)*]] $($stack)*] [] $($inner)*)
+    };
+    ([$($stack: tt)*] [$($out: tt)*] $token: tt $($rest: tt)*) => {
+        $crate::__storage_for_munch!([$($stack)*] [$($out)* $token] $($rest)*)
+    };
+}
+
+/// Family of a type that doesn't depend on the lifetime: `Of<'a> = T`
+pub struct Static<T>(PhantomData<T>);
+
+impl<T: 'static> ReuseFamily for Static<T> {
+    type Of<'a> = T;
+}
+
+/// Family of references: `Of<'a> = &'a F::Of<'a>`
+pub struct Ref<F>(PhantomData<F>);
+
+impl<F: ReuseFamily> ReuseFamily for Ref<F> {
+    type Of<'a> = &'a F::Of<'a>;
+}
+
+/// Family of slices: `Of<'a> = &'a [F::Of<'a>]`
+pub struct Slice<F>(PhantomData<F>);
+
+impl<F: ReuseFamily> ReuseFamily for Slice<F> {
+    type Of<'a> = &'a [F::Of<'a>];
+}
+
+/// Family of string slices: `Of<'a> = &'a str`
+pub struct Str;
+
+impl ReuseFamily for Str {
+    type Of<'a> = &'a str;
+}
+
+macro_rules! tuple_families {
+    ($($f: ident)+) => {
+        /// Family of tuples of the families: `Of<'a> = (F1::Of<'a>, F2::Of<'a>, ...)`
+        impl<$($f: ReuseFamily),+> ReuseFamily for ($($f,)+) {
+            type Of<'a> = ($($f::Of<'a>,)+);
+        }
+    };
+}
+tuple_families!(A);
+tuple_families!(A B);
+tuple_families!(A B C);
+tuple_families!(A B C D);
+tuple_families!(A B C D E);
+tuple_families!(A B C D E F);
+
+/// Same as `VecStorageForReuse`, but the element type is described by a `ReuseFamily` so that the lifetime of the
+/// `Vec` handed out by `reuse_allocation` is inferred:
+/// ```
+/// # use vec_storage_reuse::{family::Str, VecStorageFor};
+/// let mut words_storage: VecStorageFor<Str> = VecStorageFor::new();
+///
+/// for line in ["hello world", "foo bar baz"] {
+///     let line = line.to_owned(); // only lives this scope
+///     let mut words = words_storage.reuse_allocation();
+///     words.extend(line.split(' '));
+///     assert!(words.len() >= 2);
+/// }
+/// ```
+pub struct VecStorageFor<F: ReuseFamily> {
+    inner: VecStorageForReuse<F::Of<'static>>,
+}
+
+impl<F: ReuseFamily> VecStorageFor<F> {
+    pub fn new() -> Self {
+        Self {
+            inner: VecStorageForReuse::new(),
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            inner: VecStorageForReuse::with_capacity(capacity),
+        }
+    }
+
+    /// Uses the inner storage to provide a `VecStorageReuse: DerefMut<Target = Vec<F::Of<'a>>>`
+    ///
+    /// This avoids reallocating a new `Vec<F::Of<'a>>`.
+    pub fn reuse_allocation<'a>(&mut self) -> VecStorageReuse<'_, F::Of<'a>, F::Of<'static>> {
+        self.inner.reuse_allocation()
+    }
+
+    /// Calls `f` with a `Vec<F::Of<'a>>` that reuses the inner storage, and returns its result
+    ///
+    /// The lifetime `'a` is inferred, so the closure doesn't need any type annotation:
+    /// ```
+    /// # use vec_storage_reuse::{storage_for, VecStorageFor};
+    /// let mut storage: VecStorageFor<storage_for!(&'_ str)> = VecStorageFor::new();
+    /// let line = String::from("hello world");
+    /// let n_words = storage.with_reused(|words| {
+    ///     words.extend(line.split(' '));
+    ///     words.len()
+    /// });
+    /// assert_eq!(n_words, 2);
+    /// ```
+    pub fn with_reused<'a, R>(&mut self, f: impl FnOnce(&mut Vec<F::Of<'a>>) -> R) -> R {
+        f(&mut self.reuse_allocation())
+    }
+
+    pub fn from_vec(vec_to_use_as_storage: Vec<F::Of<'_>>) -> Self {
+        Self {
+            inner: VecStorageForReuse::from_vec(recycle::recycle(vec_to_use_as_storage)),
+        }
+    }
+
+    pub fn into_inner(self) -> VecStorageForReuse<F::Of<'static>> {
+        self.inner
+    }
+}
+
+impl<F: ReuseFamily> Default for VecStorageFor<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,222 @@
+use crate::ReuseFamily;
+
+use std::{
+    collections::{hash_map::RandomState, HashMap, HashSet},
+    mem::{self, ManuallyDrop},
+    ops::{Deref, DerefMut, Drop},
+    ptr,
+};
+
+/// Stores an empty `HashMap` and prevents it from being accessed in any other ways than through reusing its
+/// allocation for keys and values of other lifetimes
+///
+/// The keys and values are described by `ReuseFamily`s, so the reused map has exactly the same bucket layout as
+/// the stored one.
+/// ```
+/// # use vec_storage_reuse::{family::{Static, Str}, HashMapStorageForReuse};
+/// let mut counts_storage: HashMapStorageForReuse<Str, Static<usize>> = HashMapStorageForReuse::new();
+///
+/// for chunk in ["a b a", "c d c"] {
+///     let chunk = chunk.to_owned(); // only lives this scope
+///     let mut counts = counts_storage.reuse_allocation();
+///     for word in chunk.split(' ') {
+///         *counts.entry(word).or_insert(0) += 1;
+///     }
+///     assert_eq!(counts.len(), 2);
+/// }
+/// ```
+pub struct HashMapStorageForReuse<K: ReuseFamily, V: ReuseFamily, S = RandomState> {
+    /// Only `None` while the allocation is handed out, or if a guard was leaked
+    inner: Option<HashMap<K::Of<'static>, V::Of<'static>, S>>,
+}
+
+impl<K: ReuseFamily, V: ReuseFamily> HashMapStorageForReuse<K, V> {
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K: ReuseFamily, V: ReuseFamily, S> HashMapStorageForReuse<K, V, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self {
+            inner: Some(HashMap::with_hasher(hash_builder)),
+        }
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self {
+            inner: Some(HashMap::with_capacity_and_hasher(capacity, hash_builder)),
+        }
+    }
+
+    /// Uses the inner map to provide a `HashMapStorageReuse: DerefMut<Target = HashMap<K::Of<'a>, V::Of<'a>, S>>`
+    ///
+    /// This avoids reallocating a new `HashMap`.
+    /// A new hasher is only created if a previous guard was leaked.
+    pub fn reuse_allocation<'a>(&mut self) -> HashMapStorageReuse<'_, 'a, K, V, S>
+    where
+        S: Default,
+    {
+        let inner = self
+            .inner
+            .take()
+            .unwrap_or_else(|| HashMap::with_hasher(S::default()));
+        // Safety: the types only differ by their lifetimes, and the map is empty
+        let inner = unsafe { cast_lifetimes(inner) };
+        HashMapStorageReuse {
+            storage: &mut self.inner,
+            inner: ManuallyDrop::new(inner),
+        }
+    }
+}
+
+impl<K: ReuseFamily, V: ReuseFamily> Default for HashMapStorageForReuse<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Safety: inner map is always empty
+unsafe impl<K: ReuseFamily, V: ReuseFamily, S: Send> Send for HashMapStorageForReuse<K, V, S> {}
+/// Safety: inner map is always empty
+unsafe impl<K: ReuseFamily, V: ReuseFamily, S: Sync> Sync for HashMapStorageForReuse<K, V, S> {}
+
+/// Implements `DerefMut<Target = HashMap<K::Of<'a>, V::Of<'a>, S>>`, and puts the allocation back in place
+/// in the source `HashMapStorageForReuse` once dropped
+pub struct HashMapStorageReuse<'s, 'a, K: ReuseFamily, V: ReuseFamily, S> {
+    storage: &'s mut Option<HashMap<K::Of<'static>, V::Of<'static>, S>>,
+    inner: ManuallyDrop<HashMap<K::Of<'a>, V::Of<'a>, S>>,
+}
+
+impl<K: ReuseFamily, V: ReuseFamily, S> Drop for HashMapStorageReuse<'_, '_, K, V, S> {
+    fn drop(&mut self) {
+        self.inner.clear();
+        // Safety: the types only differ by their lifetimes, and the map is empty.
+        // `inner` is not used after this.
+        *self.storage = Some(unsafe { cast_lifetimes(ManuallyDrop::take(&mut self.inner)) });
+    }
+}
+
+impl<'a, K: ReuseFamily, V: ReuseFamily, S> Deref for HashMapStorageReuse<'_, 'a, K, V, S> {
+    type Target = HashMap<K::Of<'a>, V::Of<'a>, S>;
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+impl<K: ReuseFamily, V: ReuseFamily, S> DerefMut for HashMapStorageReuse<'_, '_, K, V, S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+/// Same as `HashMapStorageForReuse`, for `HashSet`s
+/// ```
+/// # use vec_storage_reuse::{family::Str, HashSetStorageForReuse};
+/// let mut seen_storage: HashSetStorageForReuse<Str> = HashSetStorageForReuse::new();
+///
+/// for chunk in ["a b a", "c c"] {
+///     let chunk = chunk.to_owned(); // only lives this scope
+///     let mut seen = seen_storage.reuse_allocation();
+///     let n_duplicates = chunk.split(' ').filter(|word| !seen.insert(word)).count();
+///     assert_eq!(n_duplicates, 1);
+/// }
+/// ```
+pub struct HashSetStorageForReuse<K: ReuseFamily, S = RandomState> {
+    /// Only `None` while the allocation is handed out, or if a guard was leaked
+    inner: Option<HashSet<K::Of<'static>, S>>,
+}
+
+impl<K: ReuseFamily> HashSetStorageForReuse<K> {
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K: ReuseFamily, S> HashSetStorageForReuse<K, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self {
+            inner: Some(HashSet::with_hasher(hash_builder)),
+        }
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self {
+            inner: Some(HashSet::with_capacity_and_hasher(capacity, hash_builder)),
+        }
+    }
+
+    /// Uses the inner set to provide a `HashSetStorageReuse: DerefMut<Target = HashSet<K::Of<'a>, S>>`
+    ///
+    /// This avoids reallocating a new `HashSet`.
+    /// A new hasher is only created if a previous guard was leaked.
+    pub fn reuse_allocation<'a>(&mut self) -> HashSetStorageReuse<'_, 'a, K, S>
+    where
+        S: Default,
+    {
+        let inner = self
+            .inner
+            .take()
+            .unwrap_or_else(|| HashSet::with_hasher(S::default()));
+        // Safety: the types only differ by their lifetimes, and the set is empty
+        let inner = unsafe { cast_lifetimes(inner) };
+        HashSetStorageReuse {
+            storage: &mut self.inner,
+            inner: ManuallyDrop::new(inner),
+        }
+    }
+}
+
+impl<K: ReuseFamily> Default for HashSetStorageForReuse<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Safety: inner set is always empty
+unsafe impl<K: ReuseFamily, S: Send> Send for HashSetStorageForReuse<K, S> {}
+/// Safety: inner set is always empty
+unsafe impl<K: ReuseFamily, S: Sync> Sync for HashSetStorageForReuse<K, S> {}
+
+/// Implements `DerefMut<Target = HashSet<K::Of<'a>, S>>`, and puts the allocation back in place
+/// in the source `HashSetStorageForReuse` once dropped
+pub struct HashSetStorageReuse<'s, 'a, K: ReuseFamily, S> {
+    storage: &'s mut Option<HashSet<K::Of<'static>, S>>,
+    inner: ManuallyDrop<HashSet<K::Of<'a>, S>>,
+}
+
+impl<K: ReuseFamily, S> Drop for HashSetStorageReuse<'_, '_, K, S> {
+    fn drop(&mut self) {
+        self.inner.clear();
+        // Safety: the types only differ by their lifetimes, and the set is empty.
+        // `inner` is not used after this.
+        *self.storage = Some(unsafe { cast_lifetimes(ManuallyDrop::take(&mut self.inner)) });
+    }
+}
+
+impl<'a, K: ReuseFamily, S> Deref for HashSetStorageReuse<'_, 'a, K, S> {
+    type Target = HashSet<K::Of<'a>, S>;
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+impl<K: ReuseFamily, S> DerefMut for HashSetStorageReuse<'_, '_, K, S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+/// # Safety
+/// `A` and `B` must be the same type, except for lifetimes
+unsafe fn cast_lifetimes<A, B>(value: A) -> B {
+    debug_assert_eq!(mem::size_of::<A>(), mem::size_of::<B>());
+    let value = ManuallyDrop::new(value);
+    ptr::read(&*value as *const A as *const B)
+}
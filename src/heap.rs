@@ -0,0 +1,33 @@
+use crate::{StorageReuse, VecStorageForReuse};
+
+use std::collections::BinaryHeap;
+
+/// Implements `DerefMut<Target = BinaryHeap<T>>`, and puts the allocation back in place
+/// in the source `Vec<S>` once dropped
+pub type BinaryHeapStorageReuse<'a, T, S> = StorageReuse<'a, BinaryHeap<T>, S>;
+
+impl<S> VecStorageForReuse<S> {
+    /// Uses the inner `Vec<S>` storage to provide a `BinaryHeapStorageReuse: DerefMut<Target = BinaryHeap<T>>`
+    ///
+    /// This avoids reallocating a new `BinaryHeap<T>`: converting between `Vec` and `BinaryHeap` never reallocates.
+    /// `T` must have the same alignment as `S`, which is checked at compile time.
+    /// ```
+    /// # use vec_storage_reuse::VecStorageForReuse;
+    /// use std::cmp::Reverse;
+    ///
+    /// let mut storage: VecStorageForReuse<(u32, &'static str)> = VecStorageForReuse::new();
+    /// let docs = vec![String::from("a"), String::from("b"), String::from("c")];
+    ///
+    /// let mut top_2 = storage.reuse_as_heap::<Reverse<(u32, &str)>>();
+    /// for (&score, doc) in [3, 1, 2].iter().zip(&docs) {
+    ///     top_2.push(Reverse((score, doc.as_str())));
+    ///     if top_2.len() > 2 {
+    ///         top_2.pop();
+    ///     }
+    /// }
+    /// assert_eq!(top_2.peek(), Some(&Reverse((2, "c"))));
+    /// ```
+    pub fn reuse_as_heap<T: Ord>(&mut self) -> BinaryHeapStorageReuse<'_, T, S> {
+        self.reuse_as()
+    }
+}
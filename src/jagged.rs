@@ -0,0 +1,127 @@
+use crate::{VecStorageForReuse, VecStorageReuse};
+
+use std::ops::Drop;
+
+/// Stores the allocations of a jagged 2D array: one `Vec` for all the values, and one for the offsets of the rows
+///
+/// This replaces a `Vec<Vec<T>>` with two reused allocations.
+/// ```
+/// # use vec_storage_reuse::JaggedVecStorage;
+/// let mut groups_storage: JaggedVecStorage<&'static str> = JaggedVecStorage::new();
+///
+/// for chunk in ["a b, c", "d, e f g"] {
+///     let chunk = chunk.to_owned(); // only lives this scope
+///     let mut groups = groups_storage.reuse_allocation::<&str>();
+///     for group in chunk.split(", ") {
+///         groups.push_row(group.split(' '));
+///     }
+///     assert_eq!(groups.len(), 2);
+///     assert!(groups.rows().all(|row| !row.is_empty()));
+/// }
+/// ```
+pub struct JaggedVecStorage<S> {
+    data: VecStorageForReuse<S>,
+    /// Always empty outside of a guard
+    offsets: Vec<usize>,
+}
+
+impl<S> JaggedVecStorage<S> {
+    pub fn new() -> Self {
+        Self {
+            data: VecStorageForReuse::new(),
+            offsets: Vec::new(),
+        }
+    }
+
+    pub fn with_capacity(n_rows: usize, n_values: usize) -> Self {
+        Self {
+            data: VecStorageForReuse::with_capacity(n_values),
+            offsets: Vec::with_capacity(n_rows),
+        }
+    }
+
+    /// Uses the stored allocations to provide a `JaggedVecStorageReuse` with rows of `T`s
+    ///
+    /// `T` must have the same alignment as `S`, which is checked at compile time.
+    pub fn reuse_allocation<T>(&mut self) -> JaggedVecStorageReuse<'_, T, S> {
+        JaggedVecStorageReuse {
+            data: self.data.reuse_allocation(),
+            offsets: &mut self.offsets,
+        }
+    }
+}
+
+impl<S> Default for JaggedVecStorage<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Jagged 2D array of `T`s, which puts the allocations back in place in the source `JaggedVecStorage<S>` once
+/// dropped
+pub struct JaggedVecStorageReuse<'a, T, S> {
+    data: VecStorageReuse<'a, T, S>,
+    /// End offset of each row in `data`
+    offsets: &'a mut Vec<usize>,
+}
+
+impl<T, S> JaggedVecStorageReuse<'_, T, S> {
+    /// Appends a row with the values of `row`
+    pub fn push_row(&mut self, row: impl IntoIterator<Item = T>) {
+        // Values may have been left there by a previous `push_row` that panicked
+        self.data
+            .truncate(self.offsets.last().copied().unwrap_or(0));
+        self.data.extend(row);
+        self.offsets.push(self.data.len());
+    }
+
+    /// # Panics
+    /// Panics if there is no row at `index`
+    pub fn row(&self, index: usize) -> &[T] {
+        &self.data[self.row_start(index)..self.offsets[index]]
+    }
+
+    /// # Panics
+    /// Panics if there is no row at `index`
+    pub fn row_mut(&mut self, index: usize) -> &mut [T] {
+        let start = self.row_start(index);
+        &mut self.data[start..self.offsets[index]]
+    }
+
+    pub fn rows(&self) -> impl ExactSizeIterator<Item = &[T]> + '_ {
+        (0..self.len()).map(move |index| self.row(index))
+    }
+
+    /// Number of rows
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Values of all the rows, one row after the other
+    pub fn values(&self) -> &[T] {
+        &self.data[..self.offsets.last().copied().unwrap_or(0)]
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.offsets.clear();
+    }
+
+    fn row_start(&self, index: usize) -> usize {
+        match index {
+            0 => 0,
+            _ => self.offsets[index - 1],
+        }
+    }
+}
+
+impl<T, S> Drop for JaggedVecStorageReuse<'_, T, S> {
+    fn drop(&mut self) {
+        self.offsets.clear();
+        // The data allocation is put back by `VecStorageReuse`
+    }
+}
@@ -0,0 +1,138 @@
+use crate::{shared::lock, ErasedVecStorage};
+
+use std::{
+    collections::HashMap,
+    mem,
+    ops::{Deref, DerefMut, Drop},
+    sync::Mutex,
+};
+
+/// Holds allocations for `Vec`s of any element types, so that many `Vec`s of different types can reuse them
+///
+/// Like an allocator's bins, the allocations are grouped by the `(size, align)` of their element type, then by
+/// capacity size class (powers of two), so that the smallest allocation that fits a requested capacity is found
+/// quickly.
+/// ```
+/// # use vec_storage_reuse::LayoutPool;
+/// let pool = LayoutPool::new();
+///
+/// for batch in 0..3 {
+///     let mut ids = pool.reuse_allocation::<u32>(100);
+///     let mut names = pool.reuse_allocation::<&str>(100);
+///     ids.extend(0..100);
+///     names.extend(std::iter::repeat("name").take(100));
+/// }
+///
+/// // `f32` has the same layout as `u32`, and its allocation is reused
+/// assert!(pool.reuse_allocation::<f32>(50).capacity() >= 100);
+/// ```
+pub struct LayoutPool {
+    bins: Mutex<HashMap<(usize, usize), SizeClasses>>,
+}
+
+/// Allocations indexed by size class
+type SizeClasses = Vec<Vec<ErasedVecStorage>>;
+
+impl LayoutPool {
+    pub fn new() -> Self {
+        Self {
+            bins: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes the smallest allocation of the pool that fits `capacity` elements of type `T` to provide a
+    /// `LayoutPoolReuse: DerefMut<Target = Vec<T>>`, or allocates a new `Vec` with that capacity if there is none
+    pub fn reuse_allocation<T>(&self, capacity: usize) -> LayoutPoolReuse<'_, T> {
+        let inner = match self.take_storage::<T>(capacity) {
+            Some(storage) => storage.into_vec(),
+            None => Vec::with_capacity(capacity),
+        };
+        LayoutPoolReuse { pool: self, inner }
+    }
+
+    /// Number of allocations currently in the pool
+    pub fn len(&self) -> usize {
+        lock(&self.bins).values().flatten().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn take_storage<T>(&self, capacity: usize) -> Option<ErasedVecStorage> {
+        if mem::size_of::<T>() == 0 {
+            return None;
+        }
+        let mut bins = lock(&self.bins);
+        let classes = bins.get_mut(&layout_class::<T>())?;
+        // Allocations in the size class of `capacity` may be too small, but those in the next ones always fit
+        let capacity_bytes = capacity.saturating_mul(mem::size_of::<T>());
+        let (class, index) = classes
+            .iter()
+            .enumerate()
+            .skip(size_class(capacity))
+            .find_map(|(class, bin)| {
+                let (index, _) = bin
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, storage)| storage.capacity_bytes() >= capacity_bytes)
+                    .min_by_key(|(_, storage)| storage.capacity_bytes())?;
+                Some((class, index))
+            })?;
+        Some(classes[class].swap_remove(index))
+    }
+
+    fn put_back<T>(&self, vec: Vec<T>) {
+        let capacity = vec.capacity();
+        let storage = ErasedVecStorage::from_vec(vec);
+        if storage.capacity_bytes() == 0 {
+            return;
+        }
+        let mut bins = lock(&self.bins);
+        let classes = bins.entry(layout_class::<T>()).or_default();
+        let class = size_class(capacity);
+        if classes.len() <= class {
+            classes.resize_with(class + 1, Vec::new);
+        }
+        classes[class].push(storage);
+    }
+}
+
+impl Default for LayoutPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn layout_class<T>() -> (usize, usize) {
+    (mem::size_of::<T>(), mem::align_of::<T>())
+}
+
+/// Allocations with a capacity in `2^k..2^(k+1)` are in size class `k`
+fn size_class(capacity: usize) -> usize {
+    (usize::BITS - capacity.leading_zeros()).saturating_sub(1) as usize
+}
+
+/// Implements `DerefMut<Target = Vec<T>>`, and puts the allocation back in the source `LayoutPool` once dropped
+pub struct LayoutPoolReuse<'p, T> {
+    pool: &'p LayoutPool,
+    inner: Vec<T>,
+}
+
+impl<T> Drop for LayoutPoolReuse<'_, T> {
+    fn drop(&mut self) {
+        self.pool.put_back(mem::take(&mut self.inner));
+    }
+}
+
+impl<T> Deref for LayoutPoolReuse<'_, T> {
+    type Target = Vec<T>;
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+impl<T> DerefMut for LayoutPoolReuse<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
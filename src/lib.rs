@@ -55,55 +55,377 @@
 //! # }
 //! ```
 //!
+//! The same can be written with a closure, which makes the scope of the reuse explicit:
+//! ```
+//! # use std::error::Error;
+//! # use vec_storage_reuse::VecStorageForReuse;
+//! #
+//! # fn process(input: &[Object<'_>]) -> Result<(), Box<dyn Error>> {
+//! #     Ok(())
+//! # }
+//! #
+//! # struct Object<'a> {
+//! #     reference: &'a [u8],
+//! # }
+//! #
+//! # fn deserialize<'a>(input: &'a [u8], output: &mut Vec<Object<'a>>) -> Result<(), Box<dyn Error>> {
+//! #     output.push(Object { reference: input });
+//! #     Ok(())
+//! # }
+//! #
+//! # fn main() -> Result<(), Box<dyn Error>> {
+//! #    let chunks: Vec<Vec<u8>> = vec![b"hoge".to_vec(); 3];
+//! #    let mut stream = chunks.iter().map(|c| c.as_slice());
+//!     let mut objects_storage: VecStorageForReuse<Object<'static>> = VecStorageForReuse::new();
+//!
+//!     while let Some(byte_chunk) = stream.next() {
+//!         objects_storage.with_reused(|objects| {
+//!             deserialize(byte_chunk, objects)?;
+//!             process(objects)
+//!         })?;
+//!     }
+//! #
+//! #    Ok(())
+//! # }
+//! ```
+//!
+//! The element types don't need to have the same size, only the same alignment: the capacity is converted so that
+//! the same allocation is used, e.g. a `VecStorageForReuse<[u32; 4]>` can be reused as a `Vec<u32>` with 4 times
+//! the capacity.
+//!
 //! ### Credits:
-//! This crate delegates most of the unsafe functionality to the `recycle_vec` crate, and just provides
-//! an interface that abstracts the swapping with the container through `Drop`, so that one can never
+//! The reinterpretation of the allocation originally comes from the `recycle_vec` crate. This crate
+//! provides an interface that abstracts the swapping with the container through `Drop`, so that one can never
 //! forget to swap back the temporary object with the storage or empty it
 //!
-//! "Most of unsafe" because we still implement `Send`/`Sync` on `VecStorageForReuse` regardless of the
-//! inner type.
+//! We also implement `Send`/`Sync` on `VecStorageForReuse` regardless of the inner type.
 //! This is safe because any `Vec` stored in this structure is *always* empty.
 
-extern crate recycle_vec;
+pub mod family;
 
-use std::ops::{Deref, DerefMut, Drop};
+mod arena;
+mod bounded_pool;
+mod columns;
+mod container;
+mod deque;
+mod erased;
+mod hash;
+mod heap;
+mod jagged;
+mod layout_pool;
+mod local;
+mod nested;
+mod ping_pong;
+mod pod;
+mod pool;
+mod recycle;
+mod shared;
+mod split;
+mod string;
 
-/// Implements `DerefMut<Target = Vec<T>>`, and puts the allocation back in place
+pub use arena::ScratchArena;
+pub use bounded_pool::{
+    Acquire, AcquireAs, BoundedStorageReuse, BoundedVecStoragePool, BoundedVecStorageReuse,
+};
+pub use columns::{Column, ColumnTypes};
+pub use container::ReusableContainer;
+pub use deque::VecDequeStorageReuse;
+pub use erased::{ErasedVecStorage, ErasedVecStorageReuse};
+pub use family::{ReuseFamily, VecStorageFor};
+pub use hash::{
+    HashMapStorageForReuse, HashMapStorageReuse, HashSetStorageForReuse, HashSetStorageReuse,
+};
+pub use heap::BinaryHeapStorageReuse;
+pub use jagged::{JaggedVecStorage, JaggedVecStorageReuse};
+pub use layout_pool::{LayoutPool, LayoutPoolReuse};
+pub use local::{
+    set_thread_local_max_retained_bytes, thread_local_reuse, ThreadLocalVecStorageReuse,
+};
+pub use nested::{NestedStorageReuse, NestedVecStorage, NestedVecStorageReuse};
+pub use ping_pong::{PingPongReuse, PingPongStorage};
+pub use pod::{reinterpret_vec, Pod, TransparentWrapper};
+pub use pool::{PooledStorageReuse, PooledVecStorageReuse, VecStoragePool};
+pub use shared::{OwnedStorageReuse, OwnedVecStorageReuse, SharedVecStorage};
+pub use split::SplitVec;
+pub use string::{StringStorageForReuse, StringStorageReuse};
+
+/// Derives a storage struct for a struct of `Vec`s with lifetime parameters, see
+/// `vec_storage_reuse_derive::ReuseStorage`
+#[cfg(feature = "derive")]
+pub use vec_storage_reuse_derive::ReuseStorage;
+
+#[doc(hidden)]
+pub mod __private {
+    /// Used by the derive macro
+    pub fn recycle<A, B>(vec: Vec<A>) -> Vec<B> {
+        crate::recycle::recycle(vec)
+    }
+}
+
+use std::{
+    error::Error,
+    fmt,
+    marker::PhantomData,
+    mem::{self, ManuallyDrop},
+    ops::{Deref, DerefMut, Drop},
+    ptr,
+};
+
+/// Fails to compile if the two given types don't have the same size and alignment,
+/// that is, if a `VecStorageForReuse` of the first one can't be reused as a `Vec` of the second one
+/// with the same capacity.
+///
+/// This allows writing the check next to the type definitions:
+/// ```
+/// # use vec_storage_reuse::assert_same_layout;
+/// struct Object<'a> {
+///     reference: &'a [u8],
+/// }
+/// assert_same_layout!(Object<'static>, &'static [u8]);
+/// ```
+///
+/// ```compile_fail
+/// # use vec_storage_reuse::assert_same_layout;
+/// assert_same_layout!(u32, u64);
+/// ```
+#[macro_export]
+macro_rules! assert_same_layout {
+    ($source: ty, $target: ty $(,)?) => {
+        const _: () = $crate::LayoutCheck::<$source, $target>::SAME_LAYOUT;
+    };
+}
+
+/// Layout checks evaluated at compile time (post-monomorphization)
+#[doc(hidden)]
+pub struct LayoutCheck<S, T>(PhantomData<(S, T)>);
+
+impl<S, T> LayoutCheck<S, T> {
+    pub const SAME_LAYOUT: () = assert!(
+        mem::size_of::<S>() == mem::size_of::<T>() && mem::align_of::<S>() == mem::align_of::<T>(),
+        "source and target types must have the same size and alignment to reuse the allocation"
+    );
+    pub const SAME_ALIGN: () = assert!(
+        mem::align_of::<S>() == mem::align_of::<T>(),
+        "source and target types must have the same alignment to reuse the allocation"
+    );
+    pub const ALIGN_FITS: () = assert!(
+        mem::align_of::<T>() <= mem::align_of::<S>(),
+        "target type must not have a greater alignment than the source type to be stored in its allocation"
+    );
+}
+
+/// Implements `DerefMut<Target = C>`, and puts the allocation back in place
 /// in the source `Vec<S>` once dropped
-pub struct VecStorageReuse<'a, T, S> {
+pub struct StorageReuse<'a, C: ReusableContainer, S> {
     storage: &'a mut Vec<S>,
-    inner: Vec<T>,
+    inner: C,
 }
 
-impl<'a, T, S> VecStorageReuse<'a, T, S> {
+/// Implements `DerefMut<Target = Vec<T>>`, and puts the allocation back in place
+/// in the source `Vec<S>` once dropped
+pub type VecStorageReuse<'a, T, S> = StorageReuse<'a, Vec<T>, S>;
+
+impl<'a, C: ReusableContainer, S> StorageReuse<'a, C, S> {
     /// Allows re-interpreting the type of a Vec to reuse the allocation.
     /// The vector is emptied and any values contained in it will be dropped.
-    /// The target type must have the same size and alignment as the source type.
+    /// The target type must have the same alignment as the source type.
+    ///
+    /// The capacity is converted to keep the same allocation. If its size in bytes is not a multiple of the size
+    /// of the target type, it is shrunk to fit (and when put back in the storage, to fit the source type).
     ///
     /// # Panics
-    /// Panics if the size or alignment of the source and target types don't match.
+    /// Panics if the alignment of the source and target types don't match.
     pub fn new(storage: &'a mut Vec<S>) -> Self {
+        let inner = if mem::size_of::<C::Item>() == 0 {
+            // Keep the allocation in the storage, there's no use for it
+            storage.clear();
+            Vec::new()
+        } else {
+            recycle::recycle(mem::take(storage))
+        };
         Self {
-            inner: recycle_vec::VecExt::recycle(std::mem::replace(storage, Vec::new())),
+            inner: C::from_empty_vec(inner),
+            storage,
+        }
+    }
+
+    /// Same as `new`, but returns an error instead of panicking if the alignment
+    /// of the source and target types don't match.
+    ///
+    /// The storage is left untouched if an error is returned.
+    pub fn try_new(storage: &'a mut Vec<S>) -> Result<Self, ReuseError> {
+        let source = TypeLayout::of::<S>();
+        let target = TypeLayout::of::<C::Item>();
+        if source.align != target.align {
+            return Err(ReuseError { source, target });
+        }
+        Ok(Self::new(storage))
+    }
+
+    /// Empties the container and gives its allocation to a `StorageReuse: DerefMut<Target = D>` over the same
+    /// storage, without going through the storage
+    ///
+    /// `D::Item` must have the same alignment as `S`, which is checked at compile time.
+    ///
+    /// The capacity is converted directly from `C::Item` to `D::Item`, so a shrunk allocation isn't shrunk further to
+    /// fit `S`:
+    /// ```
+    /// # use vec_storage_reuse::VecStorageForReuse;
+    /// let mut storage: VecStorageForReuse<[u64; 2]> = VecStorageForReuse::with_capacity(2);
+    ///
+    /// let triples = storage.reuse_allocation::<[u64; 3]>();
+    /// assert_eq!(triples.capacity(), 1);
+    /// assert_eq!(triples.recycle_as::<Vec<u64>>().capacity(), 3);
+    /// ```
+    pub fn recycle_as<D: ReusableContainer>(self) -> StorageReuse<'a, D, S> {
+        let () = LayoutCheck::<S, D::Item>::SAME_ALIGN;
+        let (storage, inner) = self.into_parts();
+        let inner = inner.into_empty_vec();
+        if mem::size_of::<C::Item>() == 0 {
+            // The allocation was kept in the storage
+            return StorageReuse::new(storage);
+        }
+        let inner = if mem::size_of::<D::Item>() == 0 {
+            // Keep the allocation in the storage, there's no use for it
+            *storage = recycle::recycle(inner);
+            Vec::new()
+        } else {
+            recycle::recycle(inner)
+        };
+        StorageReuse {
+            inner: D::from_empty_vec(inner),
+            storage,
+        }
+    }
+
+    /// Puts the allocation back in place in the storage, and gives back the storage
+    pub(crate) fn into_storage(self) -> &'a mut Vec<S> {
+        let (storage, inner) = self.into_parts();
+        let inner = inner.into_empty_vec();
+        if mem::size_of::<C::Item>() != 0 {
+            *storage = recycle::recycle(inner);
+        }
+        storage
+    }
+
+    /// Takes the storage and the container apart, without putting the allocation back
+    fn into_parts(self) -> (&'a mut Vec<S>, C) {
+        let this = ManuallyDrop::new(self);
+        // Safety: `this` is never used nor dropped after this
+        unsafe { (ptr::read(&this.storage), ptr::read(&this.inner)) }
+    }
+}
+
+impl<'a, T, S> VecStorageReuse<'a, T, S> {
+    /// Empties the `Vec<T>` and gives its allocation to a `VecStorageReuse: DerefMut<Target = Vec<U>>` over the
+    /// same storage
+    ///
+    /// This chains phases that each need a `Vec` of a different type:
+    /// ```
+    /// # use vec_storage_reuse::VecStorageForReuse;
+    /// let mut storage: VecStorageForReuse<&'static str> = VecStorageForReuse::new();
+    /// let text = String::from("1 2 3");
+    ///
+    /// let mut tokens = storage.reuse_allocation::<&str>();
+    /// tokens.extend(text.split(' '));
+    /// let n_tokens = tokens.len();
+    ///
+    /// let mut squares = tokens.recycle_into::<(usize, usize)>();
+    /// squares.extend((1..=n_tokens).map(|n| (n, n * n)));
+    /// assert!(squares.capacity() >= n_tokens);
+    /// ```
+    ///
+    /// `U` must have the same alignment as `S`, which is checked at compile time.
+    pub fn recycle_into<U>(self) -> VecStorageReuse<'a, U, S> {
+        self.recycle_as()
+    }
+
+    /// Converts each value with `f`, keeping the values in the same allocation
+    ///
+    /// Unlike `recycle_into`, the `Vec` is not emptied:
+    /// ```
+    /// # use vec_storage_reuse::VecStorageForReuse;
+    /// struct RawToken<'a>(&'a str);
+    /// enum Token<'a> {
+    ///     Word(&'a str),
+    ///     Number(usize),
+    /// }
+    ///
+    /// let mut storage: VecStorageForReuse<RawToken<'static>> = VecStorageForReuse::new();
+    /// let text = String::from("a 1 b");
+    ///
+    /// let mut raw_tokens = storage.reuse_allocation::<RawToken<'_>>();
+    /// raw_tokens.extend(text.split(' ').map(RawToken));
+    /// let tokens = raw_tokens.map_in_place(|RawToken(token)| match token.parse() {
+    ///     Ok(number) => Token::Number(number),
+    ///     Err(_) => Token::Word(token),
+    /// });
+    /// assert!(matches!(tokens[1], Token::Number(1)));
+    /// ```
+    ///
+    /// `U` must have the same size and alignment as `T`, which is checked at compile time.
+    ///
+    /// If `f` panics, the remaining values are dropped, and the emptied allocation is put back in the storage:
+    /// ```
+    /// # use vec_storage_reuse::VecStorageForReuse;
+    /// # use std::{panic::{self, AssertUnwindSafe}, sync::atomic::{AtomicUsize, Ordering}};
+    /// static DROPS: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// struct Word(String);
+    /// impl Drop for Word {
+    ///     fn drop(&mut self) {
+    ///         DROPS.fetch_add(1, Ordering::Relaxed);
+    ///     }
+    /// }
+    ///
+    /// let mut storage: VecStorageForReuse<Word> = VecStorageForReuse::with_capacity(10);
+    /// let result = panic::catch_unwind(AssertUnwindSafe(|| {
+    ///     let mut words = storage.reuse_allocation::<Word>();
+    ///     words.extend(["a", "b", "c", "d"].iter().map(|word| Word(word.to_string())));
+    ///     let _ = words.map_in_place(|word| match word.0.as_str() {
+    ///         "c" => panic!("unexpected word"),
+    ///         _ => Word(word.0.clone() + "!"),
+    ///     });
+    /// }));
+    /// assert!(result.is_err());
+    /// // The 4 words and the 2 mapped ones are each dropped once
+    /// assert_eq!(DROPS.load(Ordering::Relaxed), 6);
+    /// assert_eq!(storage.reuse_allocation::<Word>().capacity(), 10);
+    /// ```
+    pub fn map_in_place<U>(self, mut f: impl FnMut(T) -> U) -> VecStorageReuse<'a, U, S> {
+        self.filter_map_in_place(|value| Some(f(value)))
+    }
+
+    /// Same as `map_in_place`, but the values for which `f` returns `None` are removed
+    pub fn filter_map_in_place<U>(
+        self,
+        f: impl FnMut(T) -> Option<U>,
+    ) -> VecStorageReuse<'a, U, S> {
+        let () = LayoutCheck::<T, U>::SAME_LAYOUT;
+        let (storage, inner) = self.into_parts();
+        StorageReuse {
+            inner: recycle::filter_map_in_place(inner, storage, f),
             storage,
         }
     }
 }
 
-impl<'a, T, S> Drop for VecStorageReuse<'a, T, S> {
+impl<'a, C: ReusableContainer, S> Drop for StorageReuse<'a, C, S> {
     fn drop(&mut self) {
-        *self.storage =
-            recycle_vec::VecExt::recycle(std::mem::replace(&mut self.inner, Vec::new()));
+        let inner = mem::replace(&mut self.inner, C::from_empty_vec(Vec::new())).into_empty_vec();
+        if mem::size_of::<C::Item>() != 0 {
+            *self.storage = recycle::recycle(inner);
+        }
     }
 }
 
-impl<T, S> Deref for VecStorageReuse<'_, T, S> {
-    type Target = Vec<T>;
+impl<C: ReusableContainer, S> Deref for StorageReuse<'_, C, S> {
+    type Target = C;
     fn deref(&self) -> &Self::Target {
         &self.inner
     }
 }
-impl<T, S> DerefMut for VecStorageReuse<'_, T, S> {
+impl<C: ReusableContainer, S> DerefMut for StorageReuse<'_, C, S> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.inner
     }
@@ -130,8 +452,74 @@ impl<S> VecStorageForReuse<S> {
     /// Uses the inner `Vec<S>` storage to provide a `VecStorageReuse: DerefMut<Target = Vec<T>>`
     ///
     /// This avoids reallocating a new `Vec<T>`.
+    ///
+    /// `T` must have the same alignment as `S`. This is checked at compile time:
+    /// ```compile_fail
+    /// # use vec_storage_reuse::VecStorageForReuse;
+    /// let mut storage: VecStorageForReuse<u32> = VecStorageForReuse::new();
+    /// let _ = storage.reuse_allocation::<u64>();
+    /// ```
+    ///
+    /// The capacity is converted if `T` and `S` don't have the same size:
+    /// ```
+    /// # use vec_storage_reuse::VecStorageForReuse;
+    /// let mut storage: VecStorageForReuse<[u32; 4]> = VecStorageForReuse::with_capacity(2);
+    /// assert_eq!(storage.reuse_allocation::<u32>().capacity(), 8);
+    /// ```
+    ///
+    /// If the size of the allocation is not a multiple of the size of `T`, it is shrunk to fit:
+    /// ```
+    /// # use vec_storage_reuse::VecStorageForReuse;
+    /// let mut storage: VecStorageForReuse<u64> = VecStorageForReuse::with_capacity(10);
+    /// assert_eq!(storage.reuse_allocation::<[u64; 3]>().capacity(), 3);
+    /// assert_eq!(storage.reuse_allocation::<u64>().capacity(), 9);
+    /// ```
     pub fn reuse_allocation<'a, T>(&'a mut self) -> VecStorageReuse<'a, T, S> {
-        VecStorageReuse::new(&mut self.inner)
+        self.reuse_as()
+    }
+
+    /// Uses the inner `Vec<S>` storage to provide a `StorageReuse: DerefMut<Target = C>`, for any
+    /// `ReusableContainer` `C`
+    ///
+    /// `C::Item` must have the same alignment as `S`, which is checked at compile time.
+    pub fn reuse_as<C: ReusableContainer>(&mut self) -> StorageReuse<'_, C, S> {
+        let () = LayoutCheck::<S, C::Item>::SAME_ALIGN;
+        StorageReuse::new(&mut self.inner)
+    }
+
+    /// Calls `f` with a `Vec<T>` that reuses the inner `Vec<S>` storage, and returns its result
+    ///
+    /// The allocation is put back in the storage when `f` returns.
+    /// ```
+    /// # use vec_storage_reuse::VecStorageForReuse;
+    /// let mut storage: VecStorageForReuse<&'static str> = VecStorageForReuse::new();
+    /// let line = String::from("hello world");
+    /// let n_words = storage.with_reused(|words: &mut Vec<&str>| {
+    ///     words.extend(line.split(' '));
+    ///     words.len()
+    /// });
+    /// assert_eq!(n_words, 2);
+    /// ```
+    pub fn with_reused<T, R>(&mut self, f: impl FnOnce(&mut Vec<T>) -> R) -> R {
+        f(&mut self.reuse_allocation())
+    }
+
+    /// Same as `reuse_allocation`, but the layout compatibility of `T` and `S` is checked at runtime,
+    /// and a `ReuseError` is returned if they don't match.
+    ///
+    /// This is useful when the element type is only picked at runtime:
+    /// ```
+    /// # use vec_storage_reuse::VecStorageForReuse;
+    /// let mut storage: VecStorageForReuse<u32> = VecStorageForReuse::new();
+    /// assert!(storage.try_reuse_allocation::<f32>().is_ok());
+    ///
+    /// let err = storage.try_reuse_allocation::<u64>().err().unwrap();
+    /// assert_eq!(err.target.align, 8);
+    /// ```
+    pub fn try_reuse_allocation<'a, T>(
+        &'a mut self,
+    ) -> Result<VecStorageReuse<'a, T, S>, ReuseError> {
+        VecStorageReuse::try_new(&mut self.inner)
     }
 
     pub fn from_vec(mut vec_to_use_as_storage: Vec<S>) -> Self {
@@ -158,3 +546,53 @@ impl<S> Default for VecStorageForReuse<S> {
         Self::new()
     }
 }
+
+/// Name, size and alignment of a type, as reported in a `ReuseError`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypeLayout {
+    pub name: &'static str,
+    pub size: usize,
+    pub align: usize,
+}
+
+impl TypeLayout {
+    pub fn of<T>() -> Self {
+        Self {
+            name: std::any::type_name::<T>(),
+            size: mem::size_of::<T>(),
+            align: mem::align_of::<T>(),
+        }
+    }
+}
+
+impl fmt::Display for TypeLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` (size {}, align {})",
+            self.name, self.size, self.align
+        )
+    }
+}
+
+/// Returned when attempting to reuse the allocation of a `Vec<S>` as a `Vec<T>`
+/// where the alignments of `S` and `T` don't match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReuseError {
+    /// Element type of the storage
+    pub source: TypeLayout,
+    /// Element type that was requested
+    pub target: TypeLayout,
+}
+
+impl fmt::Display for ReuseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cannot reuse allocation of {} as {}: alignments must match",
+            self.source, self.target
+        )
+    }
+}
+
+impl Error for ReuseError {}
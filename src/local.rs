@@ -0,0 +1,123 @@
+use crate::ErasedVecStorage;
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    mem,
+    ops::{Deref, DerefMut, Drop},
+};
+
+thread_local! {
+    static CACHE: RefCell<ThreadLocalCache> = RefCell::new(ThreadLocalCache {
+        storages: HashMap::new(),
+        retained_bytes: 0,
+        max_retained_bytes: usize::MAX,
+    });
+}
+
+/// One allocation per `(size, align)` class of element types
+struct ThreadLocalCache {
+    storages: HashMap<(usize, usize), ErasedVecStorage>,
+    retained_bytes: usize,
+    max_retained_bytes: usize,
+}
+
+/// Provides a `ThreadLocalVecStorageReuse: DerefMut<Target = Vec<T>>` that reuses an allocation cached for the
+/// current thread, so that no storage needs to be passed around
+///
+/// Element types that have the same size and alignment share the same cached allocation.
+/// ```
+/// # use vec_storage_reuse::thread_local_reuse;
+/// fn count_words(line: &str) -> usize {
+///     let mut words = thread_local_reuse::<&str>();
+///     words.extend(line.split(' '));
+///     words.len()
+/// }
+///
+/// for _ in 0..10 {
+///     assert_eq!(count_words("hello world"), 2);
+/// }
+///
+/// // `&str` and `(usize, usize)` have the same size and alignment
+/// assert!(thread_local_reuse::<(usize, usize)>().capacity() >= 2);
+/// ```
+pub fn thread_local_reuse<T>() -> ThreadLocalVecStorageReuse<T> {
+    let inner = if mem::size_of::<T>() == 0 {
+        None
+    } else {
+        CACHE
+            .try_with(|cache| {
+                let mut cache = cache.borrow_mut();
+                let storage = cache.storages.remove(&layout_class::<T>())?;
+                cache.retained_bytes -= storage.capacity_bytes();
+                Some(storage.into_vec())
+            })
+            .ok()
+            .flatten()
+    };
+    ThreadLocalVecStorageReuse {
+        inner: inner.unwrap_or_default(),
+    }
+}
+
+/// Sets the maximum total size in bytes of the allocations cached by `thread_local_reuse` for the current thread
+///
+/// Allocations put back once that size is reached are freed instead, and the cached allocations are all freed
+/// if they exceed the new limit. Defaults to no limit.
+pub fn set_thread_local_max_retained_bytes(max_retained_bytes: usize) {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        cache.max_retained_bytes = max_retained_bytes;
+        if cache.retained_bytes > max_retained_bytes {
+            cache.storages.clear();
+            cache.retained_bytes = 0;
+        }
+    })
+}
+
+fn layout_class<T>() -> (usize, usize) {
+    (mem::size_of::<T>(), mem::align_of::<T>())
+}
+
+/// Implements `DerefMut<Target = Vec<T>>`, and puts the allocation back in the cache of the current thread
+/// once dropped
+pub struct ThreadLocalVecStorageReuse<T> {
+    inner: Vec<T>,
+}
+
+impl<T> Drop for ThreadLocalVecStorageReuse<T> {
+    fn drop(&mut self) {
+        let storage = ErasedVecStorage::from_vec(mem::take(&mut self.inner));
+        let bytes = storage.capacity_bytes();
+        if bytes == 0 {
+            return;
+        }
+        // The cache may already be destroyed if this is dropped in another thread-local destructor
+        let _ = CACHE.try_with(|cache| {
+            let mut cache = cache.borrow_mut();
+            let cache = &mut *cache;
+            // Only keep the largest allocation of the class
+            let previous_bytes = cache
+                .storages
+                .get(&layout_class::<T>())
+                .map_or(0, ErasedVecStorage::capacity_bytes);
+            let retained_bytes = cache.retained_bytes - previous_bytes + bytes;
+            if bytes > previous_bytes && retained_bytes <= cache.max_retained_bytes {
+                cache.storages.insert(layout_class::<T>(), storage);
+                cache.retained_bytes = retained_bytes;
+            }
+        });
+    }
+}
+
+impl<T> Deref for ThreadLocalVecStorageReuse<T> {
+    type Target = Vec<T>;
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+impl<T> DerefMut for ThreadLocalVecStorageReuse<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
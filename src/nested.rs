@@ -0,0 +1,138 @@
+use crate::{recycle, LayoutCheck, ReusableContainer, StorageReuse, VecStorageForReuse};
+
+use std::{
+    mem,
+    ops::{Deref, DerefMut, Drop},
+};
+
+/// Stores the allocation of an outer `Vec` along with the allocations of its inner `Vec`s (or other
+/// `ReusableContainer`s), so that all of them are reused
+///
+/// Clearing a `Vec<Vec<T>>` drops all the inner `Vec`s with their allocations. This storage instead keeps a stash
+/// of the inner allocations, which the guard hands out again through `push_new`/`new_inner`.
+/// ```
+/// # use vec_storage_reuse::NestedVecStorage;
+/// let mut groups_storage: NestedVecStorage<&'static str> = NestedVecStorage::new();
+///
+/// for chunk in ["a b c", "d e f"] {
+///     let chunk = chunk.to_owned(); // only lives this scope
+///     let mut groups = groups_storage.reuse_allocation::<&str>();
+///     for word in chunk.split(' ') {
+///         groups.push_new().extend(std::iter::repeat(word).take(10));
+///     }
+/// }
+///
+/// // The inner allocations were kept
+/// assert_eq!(groups_storage.stashed_len(), 3);
+/// ```
+///
+/// It may also be used for `Vec<String>`s, with `NestedVecStorage<u8>` and `reuse_as::<String>`.
+pub struct NestedVecStorage<S> {
+    outer: VecStorageForReuse<Vec<S>>,
+    stash: Vec<VecStorageForReuse<S>>,
+}
+
+impl<S> NestedVecStorage<S> {
+    pub fn new() -> Self {
+        Self {
+            outer: VecStorageForReuse::new(),
+            stash: Vec::new(),
+        }
+    }
+
+    /// Uses the stored allocations to provide a `NestedVecStorageReuse: DerefMut<Target = Vec<Vec<T>>>`
+    ///
+    /// `T` must have the same alignment as `S`, which is checked at compile time.
+    pub fn reuse_allocation<T>(&mut self) -> NestedVecStorageReuse<'_, T, S> {
+        self.reuse_as()
+    }
+
+    /// Same as `reuse_allocation`, with inner containers of any `ReusableContainer` type `C`
+    pub fn reuse_as<C: ReusableContainer>(&mut self) -> NestedStorageReuse<'_, C, S> {
+        let () = LayoutCheck::<S, C::Item>::SAME_ALIGN;
+        NestedStorageReuse {
+            outer: self.outer.reuse_allocation(),
+            stash: &mut self.stash,
+        }
+    }
+
+    /// Number of inner allocations currently kept in the storage
+    pub fn stashed_len(&self) -> usize {
+        self.stash.len()
+    }
+}
+
+impl<S> Default for NestedVecStorage<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Implements `DerefMut<Target = Vec<C>>`, and puts the allocation of the outer `Vec` and of all its inner
+/// containers back in place in the source `NestedVecStorage<S>` once dropped
+pub struct NestedStorageReuse<'a, C: ReusableContainer, S> {
+    outer: StorageReuse<'a, Vec<C>, Vec<S>>,
+    stash: &'a mut Vec<VecStorageForReuse<S>>,
+}
+
+/// Implements `DerefMut<Target = Vec<Vec<T>>>`, and puts the allocation of the outer `Vec` and of all the inner
+/// `Vec`s back in place in the source `NestedVecStorage<S>` once dropped
+pub type NestedVecStorageReuse<'a, T, S> = NestedStorageReuse<'a, Vec<T>, S>;
+
+impl<C: ReusableContainer, S> NestedStorageReuse<'_, C, S> {
+    /// Provides an empty inner container, reusing a stored allocation if there is one
+    ///
+    /// A zero-sized `C::Item` doesn't use the stored allocations, which are left untouched:
+    /// ```
+    /// # use vec_storage_reuse::NestedVecStorage;
+    /// let mut storage: NestedVecStorage<u32> = NestedVecStorage::new();
+    /// storage.reuse_allocation::<u32>().push_new().push(1);
+    ///
+    /// storage.reuse_allocation::<[u32; 0]>().push_new().push([]);
+    /// assert_eq!(storage.stashed_len(), 1);
+    /// ```
+    pub fn new_inner(&mut self) -> C {
+        if mem::size_of::<C::Item>() == 0 {
+            return C::from_empty_vec(Vec::new());
+        }
+        C::from_empty_vec(match self.stash.pop() {
+            Some(storage) => recycle::recycle(storage.inner),
+            None => Vec::new(),
+        })
+    }
+
+    /// Pushes an empty inner container, reusing a stored allocation if there is one, and returns it
+    pub fn push_new(&mut self) -> &mut C {
+        let inner = self.new_inner();
+        self.outer.push(inner);
+        self.outer.last_mut().expect("We just pushed")
+    }
+}
+
+impl<C: ReusableContainer, S> Drop for NestedStorageReuse<'_, C, S> {
+    fn drop(&mut self) {
+        for inner in self.outer.drain(..) {
+            if mem::size_of::<C::Item>() == 0 {
+                // Didn't come from the stash
+                continue;
+            }
+            let inner: Vec<S> = recycle::recycle(inner.into_empty_vec());
+            if inner.capacity() != 0 {
+                self.stash.push(VecStorageForReuse { inner });
+            }
+        }
+        // The outer allocation is put back by `StorageReuse`
+    }
+}
+
+impl<C: ReusableContainer, S> Deref for NestedStorageReuse<'_, C, S> {
+    type Target = Vec<C>;
+    fn deref(&self) -> &Self::Target {
+        &self.outer
+    }
+}
+impl<C: ReusableContainer, S> DerefMut for NestedStorageReuse<'_, C, S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.outer
+    }
+}
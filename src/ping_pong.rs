@@ -0,0 +1,97 @@
+use crate::{LayoutCheck, VecStorageForReuse, VecStorageReuse};
+
+use std::ops::{Deref, DerefMut};
+
+/// Stores two allocations, so that pipeline phases can each read the `Vec` written by the previous phase and write
+/// a `Vec` of another type
+///
+/// ```
+/// # use vec_storage_reuse::PingPongStorage;
+/// let mut storage: PingPongStorage<&'static str> = PingPongStorage::new();
+///
+/// for source in ["1 + 2", "3 + 4 + 5"] {
+///     let source = source.to_owned(); // only lives this scope
+///     let mut tokens = storage.reuse_allocation::<&str>();
+///     tokens.extend(source.split(' '));
+///
+///     let numbers = tokens.phase(|tokens, numbers: &mut Vec<(usize, &str)>| {
+///         numbers.extend(tokens.iter().filter_map(|token| Some((token.parse().ok()?, *token))));
+///     });
+///     let sums = numbers.phase(|numbers, sums: &mut Vec<usize>| {
+///         sums.push(numbers.iter().map(|&(number, _)| number).sum());
+///     });
+///     assert!(sums[0] % 3 == 0);
+/// }
+/// ```
+pub struct PingPongStorage<S> {
+    front: VecStorageForReuse<S>,
+    back: VecStorageForReuse<S>,
+}
+
+impl<S> PingPongStorage<S> {
+    pub fn new() -> Self {
+        Self {
+            front: VecStorageForReuse::new(),
+            back: VecStorageForReuse::new(),
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            front: VecStorageForReuse::with_capacity(capacity),
+            back: VecStorageForReuse::with_capacity(capacity),
+        }
+    }
+
+    /// Uses one of the stored allocations to provide a `PingPongReuse: DerefMut<Target = Vec<T>>` for the first
+    /// phase
+    ///
+    /// `T` must have the same alignment as `S`, which is checked at compile time.
+    pub fn reuse_allocation<T>(&mut self) -> PingPongReuse<'_, T, S> {
+        PingPongReuse {
+            current: self.front.reuse_allocation(),
+            other: &mut self.back.inner,
+        }
+    }
+}
+
+impl<S> Default for PingPongStorage<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Implements `DerefMut<Target = Vec<T>>` over one of the allocations of a `PingPongStorage<S>`, and puts it back in
+/// place once dropped
+pub struct PingPongReuse<'a, T, S> {
+    current: VecStorageReuse<'a, T, S>,
+    other: &'a mut Vec<S>,
+}
+
+impl<'a, T, S> PingPongReuse<'a, T, S> {
+    /// Calls `f` with the current `Vec<T>` and an empty `Vec<U>` using the other allocation, then empties the
+    /// `Vec<T>` and returns the `Vec<U>` for the next phase
+    ///
+    /// `U` must have the same alignment as `S`, which is checked at compile time.
+    pub fn phase<U>(mut self, f: impl FnOnce(&mut Vec<T>, &mut Vec<U>)) -> PingPongReuse<'a, U, S> {
+        let () = LayoutCheck::<S, U>::SAME_ALIGN;
+        let mut next = VecStorageReuse::new(self.other);
+        f(&mut self.current, &mut next);
+        PingPongReuse {
+            other: self.current.into_storage(),
+            current: next,
+        }
+    }
+}
+
+impl<T, S> Deref for PingPongReuse<'_, T, S> {
+    type Target = Vec<T>;
+    fn deref(&self) -> &Self::Target {
+        &self.current
+    }
+}
+impl<T, S> DerefMut for PingPongReuse<'_, T, S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.current
+    }
+}
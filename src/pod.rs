@@ -0,0 +1,196 @@
+use crate::{recycle, LayoutCheck, StorageReuse, VecStorageReuse};
+
+use std::{
+    alloc::Layout,
+    mem::{self, ManuallyDrop},
+    ptr::NonNull,
+};
+
+/// Plain old data: types without padding nor drop glue, for which any bit pattern is a valid value
+///
+/// This allows reinterpreting the values of a `Vec` of a `Pod` type as values of another `Pod` type, without
+/// emptying it. A `#[repr(transparent)]` wrapper around a `Pod` type may implement it as well:
+/// ```
+/// # use vec_storage_reuse::{reinterpret_vec, Pod};
+/// #[derive(Clone, Copy)]
+/// #[repr(transparent)]
+/// struct Meters(u32);
+///
+/// // Safety: `Meters` is `#[repr(transparent)]` around a `Pod` type
+/// unsafe impl Pod for Meters {}
+///
+/// let distances = reinterpret_vec::<Meters, u32>(vec![Meters(3), Meters(4)]);
+/// assert_eq!(distances, [3, 4]);
+/// ```
+///
+/// # Safety
+/// Implementing types must not have padding bytes, and any bit pattern must be a valid value of the type.
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! impl_pod {
+    ($($ty: ty)*) => {
+        $(unsafe impl Pod for $ty {})*
+    };
+}
+impl_pod!(u8 u16 u32 u64 u128 usize i8 i16 i32 i64 i128 isize f32 f64);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+/// Types that are `#[repr(transparent)]` wrappers around `Inner`, so that the values of a `Vec` can be wrapped and
+/// peeled in place
+///
+/// Unlike `Pod`, this works for any `Inner`, including types with lifetimes:
+/// ```
+/// # use vec_storage_reuse::TransparentWrapper;
+/// #[repr(transparent)]
+/// struct Word<T>(T);
+///
+/// // Safety: `Word<T>` is `#[repr(transparent)]` around `T`
+/// unsafe impl<T> TransparentWrapper<T> for Word<T> {}
+///
+/// let text = String::from("a b");
+/// let words = Word::wrap_vec(text.split(' ').collect());
+/// let words: Vec<&str> = Word::peel_vec(words);
+/// assert_eq!(words, ["a", "b"]);
+/// ```
+///
+/// # Safety
+/// Implementing types must be `#[repr(transparent)]` around a field of type `Inner`, with no invariant beyond those
+/// of `Inner`.
+pub unsafe trait TransparentWrapper<Inner>: Sized {
+    /// Wraps the values of a `Vec<Inner>`, keeping them in the same allocation
+    fn wrap_vec(vec: Vec<Inner>) -> Vec<Self> {
+        let () = LayoutCheck::<Inner, Self>::SAME_LAYOUT;
+        let mut vec = ManuallyDrop::new(vec);
+        // Safety: `Self` has the same layout as `Inner`, and any `Inner` is a valid `Self`
+        unsafe { Vec::from_raw_parts(vec.as_mut_ptr() as *mut Self, vec.len(), vec.capacity()) }
+    }
+
+    /// Peels the values of a `Vec<Self>`, keeping them in the same allocation
+    fn peel_vec(vec: Vec<Self>) -> Vec<Inner> {
+        let () = LayoutCheck::<Self, Inner>::SAME_LAYOUT;
+        let mut vec = ManuallyDrop::new(vec);
+        // Safety: `Self` has the same layout as `Inner`, and wraps a valid `Inner`
+        unsafe { Vec::from_raw_parts(vec.as_mut_ptr() as *mut Inner, vec.len(), vec.capacity()) }
+    }
+}
+
+/// Reinterprets the values of a `Vec<T>` as values of `U`, keeping them in the same allocation
+///
+/// The length and capacity are converted so that they keep the same size in bytes. If the size in bytes of the
+/// capacity is not a multiple of `size_of::<U>()`, the allocation is shrunk, keeping the values.
+/// ```
+/// # use vec_storage_reuse::reinterpret_vec;
+/// let pairs: Vec<[u32; 2]> = vec![[1, 2], [3, 4]];
+/// assert_eq!(reinterpret_vec::<[u32; 2], u32>(pairs), [1, 2, 3, 4]);
+/// ```
+///
+/// `U` must have the same alignment as `T`, which is checked at compile time. As with bytemuck's `try_cast_vec`, a
+/// `Vec<u32>` can't become a `Vec<[u8; 4]>`, because the allocation must be freed with the alignment it was allocated
+/// with:
+/// ```compile_fail
+/// # use vec_storage_reuse::reinterpret_vec;
+/// let bytes = reinterpret_vec::<u32, [u8; 4]>(vec![1, 2]);
+/// ```
+///
+/// The bytes need to be copied to a new allocation instead:
+/// ```
+/// let words: Vec<u32> = vec![1, 2];
+/// let bytes: Vec<[u8; 4]> = words.iter().map(|word| word.to_ne_bytes()).collect();
+/// ```
+///
+/// # Panics
+/// Panics if `U` is zero-sized, or if the size in bytes of the values is not a multiple of `size_of::<U>()`.
+pub fn reinterpret_vec<T: Pod, U: Pod>(vec: Vec<T>) -> Vec<U> {
+    let () = LayoutCheck::<T, U>::SAME_ALIGN;
+    check_reinterpret::<T, U>(vec.len());
+    let (size_t, size_u) = (mem::size_of::<T>(), mem::size_of::<U>());
+    let bytes = vec.len() * size_t;
+    if size_t == 0 || vec.capacity() == 0 {
+        return Vec::new();
+    }
+    let mut vec = ManuallyDrop::new(vec);
+    // Safety: the `Vec` has an allocation so its pointer is not null, and this is the layout it allocated with
+    let (ptr, layout) = unsafe {
+        (
+            NonNull::new_unchecked(vec.as_mut_ptr() as *mut u8),
+            Layout::from_size_align_unchecked(vec.capacity() * size_t, mem::align_of::<T>()),
+        )
+    };
+    // Safety: the allocation comes from a `Vec`. The alignments match so it is reused, and if it is shrunk, the
+    // values are kept since `bytes` fits in the new capacity.
+    let mut reinterpreted: Vec<U> = unsafe { recycle::vec_from_allocation(ptr, layout) };
+    // Safety: the `bytes` first bytes are initialized, and any bit pattern is a valid `U`
+    unsafe { reinterpreted.set_len(bytes / size_u) };
+    reinterpreted
+}
+
+/// Panics if `len` values of `T` can't be reinterpreted as values of `U`
+fn check_reinterpret<T, U>(len: usize) {
+    let size_u = mem::size_of::<U>();
+    assert_ne!(
+        size_u, 0,
+        "target type must not be zero-sized to reinterpret the values"
+    );
+    assert_eq!(
+        len * mem::size_of::<T>() % size_u,
+        0,
+        "the size of the values must be a multiple of the size of the target type to reinterpret them"
+    );
+}
+
+impl<'a, T: Pod, S> VecStorageReuse<'a, T, S> {
+    /// Reinterprets the values of the `Vec<T>` as values of `U`, keeping them in the same allocation, which is then
+    /// put back in the same storage
+    ///
+    /// Unlike `recycle_into`, the values are kept:
+    /// ```
+    /// # use vec_storage_reuse::VecStorageForReuse;
+    /// let mut storage: VecStorageForReuse<u32> = VecStorageForReuse::new();
+    ///
+    /// let mut words = storage.reuse_allocation::<u32>();
+    /// words.extend([0x3f80_0000, 0x4000_0000].iter());
+    /// let floats = words.reinterpret::<f32>();
+    /// assert_eq!(*floats, [1.0, 2.0]);
+    /// ```
+    ///
+    /// The allocation of a zero-sized `T` stays in the storage:
+    /// ```
+    /// # use vec_storage_reuse::VecStorageForReuse;
+    /// let mut storage: VecStorageForReuse<u32> = VecStorageForReuse::with_capacity(100);
+    /// let words = storage.reuse_allocation::<[u32; 0]>().reinterpret::<u32>();
+    /// assert_eq!(words.capacity(), 100);
+    /// ```
+    ///
+    /// `U` must have the same alignment as `T`, which is checked at compile time.
+    ///
+    /// # Panics
+    /// Same as `reinterpret_vec`. The allocation is put back in the storage beforehand:
+    /// ```
+    /// # use vec_storage_reuse::VecStorageForReuse;
+    /// # use std::panic::{self, AssertUnwindSafe};
+    /// let mut storage: VecStorageForReuse<u32> = VecStorageForReuse::with_capacity(99);
+    ///
+    /// let result = panic::catch_unwind(AssertUnwindSafe(|| {
+    ///     let mut triples = storage.reuse_allocation::<[u32; 3]>();
+    ///     triples.push([1, 2, 3]);
+    ///     triples.reinterpret::<[u32; 2]>().len()
+    /// }));
+    /// assert!(result.is_err());
+    /// assert_eq!(storage.reuse_allocation::<u32>().capacity(), 99);
+    /// ```
+    pub fn reinterpret<U: Pod>(self) -> VecStorageReuse<'a, U, S> {
+        let () = LayoutCheck::<T, U>::SAME_ALIGN;
+        // Checked while the guard still puts the allocation back if this panics
+        check_reinterpret::<T, U>(self.len());
+        if mem::size_of::<T>() == 0 {
+            // There are no bytes to reinterpret, and the allocation was kept in the storage
+            return StorageReuse::new(self.into_storage());
+        }
+        let (storage, inner) = self.into_parts();
+        StorageReuse {
+            inner: reinterpret_vec(inner),
+            storage,
+        }
+    }
+}
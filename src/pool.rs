@@ -0,0 +1,159 @@
+use crate::{recycle, shared::lock, LayoutCheck, ReusableContainer, VecStorageForReuse};
+
+use std::{
+    mem,
+    ops::{Deref, DerefMut, Drop},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+/// Holds many allocations, so that several `Vec`s can reuse them at the same time, possibly from different
+/// threads
+///
+/// The allocations are split in shards, each thread using its own shard first and only looking into the other
+/// ones when its own is empty, so that threads don't contend on the same lock.
+/// ```
+/// # use vec_storage_reuse::VecStoragePool;
+/// let pool: VecStoragePool<&'static str> = VecStoragePool::new();
+///
+/// std::thread::scope(|s| {
+///     for _ in 0..4 {
+///         s.spawn(|| {
+///             for _ in 0..10 {
+///                 let line = String::from("hello world");
+///                 let mut words = pool.reuse_allocation::<&str>();
+///                 words.extend(line.split(' '));
+///             }
+///         });
+///     }
+/// });
+/// ```
+pub struct VecStoragePool<S> {
+    shards: Box<[Mutex<Vec<VecStorageForReuse<S>>>]>,
+}
+
+impl<S> VecStoragePool<S> {
+    /// Creates a pool with as many shards as the available parallelism
+    pub fn new() -> Self {
+        Self::with_shards(std::thread::available_parallelism().map_or(1, |n| n.get()))
+    }
+
+    /// # Panics
+    /// Panics if `n_shards` is 0
+    pub fn with_shards(n_shards: usize) -> Self {
+        assert!(n_shards > 0, "A VecStoragePool needs at least one shard");
+        Self {
+            shards: (0..n_shards).map(|_| Mutex::new(Vec::new())).collect(),
+        }
+    }
+
+    /// Takes an allocation from the pool to provide a `PooledVecStorageReuse: DerefMut<Target = Vec<T>>`,
+    /// or starts with a new `Vec` if the pool is empty
+    ///
+    /// `T` must have the same alignment as `S`, which is checked at compile time.
+    ///
+    /// A zero-sized `T` doesn't use the allocations of the pool, and doesn't add any:
+    /// ```
+    /// # use vec_storage_reuse::VecStoragePool;
+    /// let pool: VecStoragePool<()> = VecStoragePool::new();
+    /// for _ in 0..5 {
+    ///     pool.reuse_allocation::<()>().push(());
+    /// }
+    /// assert_eq!(pool.len(), 0);
+    /// ```
+    pub fn reuse_allocation<T>(&self) -> PooledVecStorageReuse<'_, T, S> {
+        self.reuse_as()
+    }
+
+    /// Same as `reuse_allocation`, for any `ReusableContainer` `C`
+    pub fn reuse_as<C: ReusableContainer>(&self) -> PooledStorageReuse<'_, C, S> {
+        let () = LayoutCheck::<S, C::Item>::SAME_ALIGN;
+        let inner = if mem::size_of::<C::Item>() == 0 {
+            Vec::new()
+        } else {
+            self.take_storage()
+                .map_or_else(Vec::new, |storage| recycle::recycle(storage.inner))
+        };
+        PooledStorageReuse {
+            pool: self,
+            inner: C::from_empty_vec(inner),
+        }
+    }
+
+    /// Number of allocations currently in the pool
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| lock(shard).len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn take_storage(&self) -> Option<VecStorageForReuse<S>> {
+        let own = self.shard_index();
+        if let Some(storage) = lock(&self.shards[own]).pop() {
+            return Some(storage);
+        }
+        // Steal from other shards, without waiting for them
+        (1..self.shards.len())
+            .map(|offset| &self.shards[(own + offset) % self.shards.len()])
+            .find_map(|shard| shard.try_lock().ok()?.pop())
+    }
+
+    fn put_back(&self, storage: VecStorageForReuse<S>) {
+        lock(&self.shards[self.shard_index()]).push(storage);
+    }
+
+    fn shard_index(&self) -> usize {
+        static NEXT_THREAD_INDEX: AtomicUsize = AtomicUsize::new(0);
+        thread_local! {
+            static THREAD_INDEX: usize = NEXT_THREAD_INDEX.fetch_add(1, Ordering::Relaxed);
+        }
+        THREAD_INDEX.with(|&index| index % self.shards.len())
+    }
+}
+
+impl<S> Default for VecStoragePool<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Implements `DerefMut<Target = C>`, and puts the allocation back in the source `VecStoragePool<S>`
+/// once dropped
+pub struct PooledStorageReuse<'p, C: ReusableContainer, S> {
+    pool: &'p VecStoragePool<S>,
+    inner: C,
+}
+
+/// Implements `DerefMut<Target = Vec<T>>`, and puts the allocation back in the source `VecStoragePool<S>`
+/// once dropped
+pub type PooledVecStorageReuse<'p, T, S> = PooledStorageReuse<'p, Vec<T>, S>;
+
+impl<C: ReusableContainer, S> Drop for PooledStorageReuse<'_, C, S> {
+    fn drop(&mut self) {
+        let inner = mem::replace(&mut self.inner, C::from_empty_vec(Vec::new())).into_empty_vec();
+        if mem::size_of::<C::Item>() == 0 {
+            // Didn't come from the pool
+            return;
+        }
+        let storage: Vec<S> = recycle::recycle(inner);
+        if storage.capacity() != 0 {
+            self.pool.put_back(VecStorageForReuse { inner: storage });
+        }
+    }
+}
+
+impl<C: ReusableContainer, S> Deref for PooledStorageReuse<'_, C, S> {
+    type Target = C;
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+impl<C: ReusableContainer, S> DerefMut for PooledStorageReuse<'_, C, S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
@@ -0,0 +1,153 @@
+use std::{
+    alloc::{self, Layout},
+    marker::PhantomData,
+    mem::{self, ManuallyDrop},
+    ptr::{self, NonNull},
+};
+
+/// Reinterprets the allocation of a `Vec<A>` as the allocation of a `Vec<B>`.
+/// The vector is emptied and any values contained in it will be dropped.
+///
+/// The capacity is converted so that the allocation keeps the same size in bytes. If that size is not a multiple of
+/// `size_of::<B>()`, the allocation is shrunk to the largest such multiple, because `Vec<B>` will later deallocate
+/// it assuming its size is `capacity * size_of::<B>()`.
+///
+/// # Panics
+/// Panics if the alignments of `A` and `B` don't match: `Vec<B>` deallocates with `align_of::<B>()`, which must be
+/// the alignment the memory was allocated with.
+pub(crate) fn recycle<A, B>(vec: Vec<A>) -> Vec<B> {
+    assert_eq!(
+        mem::align_of::<A>(),
+        mem::align_of::<B>(),
+        "source and target types must have the same alignment to reuse the allocation"
+    );
+    match into_allocation(vec) {
+        // Safety: the allocation comes from a `Vec`
+        Some((ptr, layout)) => unsafe { vec_from_allocation(ptr, layout) },
+        None => Vec::new(),
+    }
+}
+
+/// Empties the vector and takes ownership of its allocation, if it has one
+pub(crate) fn into_allocation<A>(mut vec: Vec<A>) -> Option<(NonNull<u8>, Layout)> {
+    vec.clear();
+    if mem::size_of::<A>() == 0 || vec.capacity() == 0 {
+        return None;
+    }
+    // Safety: this is the layout the `Vec` allocated with, so it is valid
+    let layout = unsafe {
+        Layout::from_size_align_unchecked(
+            vec.capacity() * mem::size_of::<A>(),
+            mem::align_of::<A>(),
+        )
+    };
+    let mut vec = ManuallyDrop::new(vec);
+    // Safety: the `Vec` has an allocation so its pointer is not null
+    let ptr = unsafe { NonNull::new_unchecked(vec.as_mut_ptr() as *mut u8) };
+    Some((ptr, layout))
+}
+
+/// Turns an allocation into an empty `Vec<B>`, keeping as much of it as possible.
+///
+/// If the alignment of the allocation is the one of `B`, the allocation is reused, and shrunk if its size is not a
+/// multiple of `size_of::<B>()`. Otherwise it is deallocated and a new allocation of (at most) the same size is
+/// made.
+///
+/// # Safety
+/// `ptr` must have been allocated by the global allocator with `layout`, which must have a non-zero size.
+/// Ownership of the allocation is transferred to this function.
+pub(crate) unsafe fn vec_from_allocation<B>(ptr: NonNull<u8>, layout: Layout) -> Vec<B> {
+    let (size_b, align_b) = (mem::size_of::<B>(), mem::align_of::<B>());
+    let capacity = layout.size().checked_div(size_b).unwrap_or(0);
+    if capacity == 0 || layout.align() != align_b {
+        alloc::dealloc(ptr.as_ptr(), layout);
+        return Vec::with_capacity(capacity);
+    }
+    let mut ptr = ptr.as_ptr();
+    let new_size = capacity * size_b;
+    if new_size != layout.size() {
+        // `new_size` is non-zero and smaller than `layout.size()` so it can't overflow `isize`
+        ptr = alloc::realloc(ptr, layout, new_size);
+        if ptr.is_null() {
+            alloc::handle_alloc_error(Layout::from_size_align_unchecked(new_size, align_b));
+        }
+    }
+    // `ptr` was allocated with alignment `align_of::<B>()` and size `capacity * size_of::<B>()`
+    Vec::from_raw_parts(ptr as *mut B, 0, capacity)
+}
+
+/// Converts the values of a `Vec<T>` with `f` in its allocation, removing those for which it returns `None`
+///
+/// If `f` panics, the values that remain are dropped and the emptied allocation is put back in `storage`.
+///
+/// # Panics
+/// Panics if `T` and `U` don't have the same size and alignment.
+pub(crate) fn filter_map_in_place<S, T, U>(
+    vec: Vec<T>,
+    storage: &mut Vec<S>,
+    mut f: impl FnMut(T) -> Option<U>,
+) -> Vec<U> {
+    assert!(
+        mem::size_of::<T>() == mem::size_of::<U>() && mem::align_of::<T>() == mem::align_of::<U>(),
+        "source and target types must have the same size and alignment to convert the values in place"
+    );
+    let mut vec = ManuallyDrop::new(vec);
+    let mut progress = Progress::<S, T, U> {
+        storage,
+        ptr: vec.as_mut_ptr(),
+        len: vec.len(),
+        capacity: vec.capacity(),
+        read: 0,
+        written: 0,
+        _marker: PhantomData,
+    };
+    while progress.read < progress.len {
+        // Safety: `read < len`, and the value at `read` was not moved yet
+        let value = unsafe { progress.ptr.add(progress.read).read() };
+        progress.read += 1;
+        if let Some(value) = f(value) {
+            // Safety: `written < read`, so the value that was there was already moved out
+            unsafe { (progress.ptr.add(progress.written) as *mut U).write(value) };
+            progress.written += 1;
+        }
+    }
+    let progress = ManuallyDrop::new(progress);
+    // Safety: `T` and `U` have the same layout, and the `written` first values are `U`s
+    unsafe { Vec::from_raw_parts(progress.ptr as *mut U, progress.written, progress.capacity) }
+}
+
+/// State of `filter_map_in_place`, which drops the values and puts the emptied allocation back in the storage if
+/// `f` panics
+struct Progress<'s, S, T, U> {
+    storage: &'s mut Vec<S>,
+    ptr: *mut T,
+    len: usize,
+    capacity: usize,
+    /// The values before `read` were moved out
+    read: usize,
+    /// The values before `written` are `U`s
+    written: usize,
+    _marker: PhantomData<U>,
+}
+
+impl<S, T, U> Drop for Progress<'_, S, T, U> {
+    fn drop(&mut self) {
+        // Safety: the `written` first values are `U`s, and the values from `read` to `len` are `T`s which were not
+        // moved out. Only the emptied allocation is left afterwards.
+        let allocation = unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(
+                self.ptr as *mut U,
+                self.written,
+            ));
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(
+                self.ptr.add(self.read),
+                self.len - self.read,
+            ));
+            Vec::from_raw_parts(self.ptr, 0, self.capacity)
+        };
+        // The allocation of a zero-sized `T` was kept in the storage
+        if mem::size_of::<T>() != 0 {
+            *self.storage = recycle(allocation);
+        }
+    }
+}
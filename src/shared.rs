@@ -0,0 +1,123 @@
+use crate::{recycle, LayoutCheck, ReusableContainer, VecStorageForReuse};
+
+use std::{
+    mem,
+    ops::{Deref, DerefMut, Drop},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+/// Same as `VecStorageForReuse`, but may be cloned and shared across threads, and hands out guards that don't
+/// borrow it
+///
+/// This allows keeping the reused `Vec` across `.await` points, or moving it to another thread:
+/// ```
+/// # use vec_storage_reuse::SharedVecStorage;
+/// let storage: SharedVecStorage<u64> = SharedVecStorage::with_capacity(100);
+///
+/// let mut numbers = storage.reuse_allocation::<i64>();
+/// std::thread::spawn(move || {
+///     numbers.extend(0..100);
+///     // The allocation goes back to `storage` here
+/// })
+/// .join()
+/// .unwrap();
+///
+/// assert_eq!(storage.reuse_allocation::<u64>().capacity(), 100);
+/// ```
+///
+/// There is only one allocation in the storage: while it is handed out, further guards start with a new `Vec`.
+/// When several guards are put back, the largest allocation is kept.
+pub struct SharedVecStorage<S> {
+    inner: Arc<Mutex<VecStorageForReuse<S>>>,
+}
+
+impl<S> SharedVecStorage<S> {
+    pub fn new() -> Self {
+        Self::from_storage(VecStorageForReuse::new())
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::from_storage(VecStorageForReuse::with_capacity(capacity))
+    }
+
+    pub fn from_storage(storage: VecStorageForReuse<S>) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(storage)),
+        }
+    }
+
+    /// Uses the shared `Vec<S>` storage to provide an `OwnedVecStorageReuse: DerefMut<Target = Vec<T>>`
+    ///
+    /// This avoids reallocating a new `Vec<T>`, unless the allocation is already handed out.
+    /// `T` must have the same alignment as `S`, which is checked at compile time.
+    pub fn reuse_allocation<T>(&self) -> OwnedVecStorageReuse<T, S> {
+        self.reuse_as()
+    }
+
+    /// Same as `reuse_allocation`, for any `ReusableContainer` `C`
+    pub fn reuse_as<C: ReusableContainer>(&self) -> OwnedStorageReuse<C, S> {
+        let () = LayoutCheck::<S, C::Item>::SAME_ALIGN;
+        let inner = if mem::size_of::<C::Item>() == 0 {
+            Vec::new()
+        } else {
+            recycle::recycle(mem::take(&mut lock(&self.inner).inner))
+        };
+        OwnedStorageReuse {
+            storage: Arc::clone(&self.inner),
+            inner: C::from_empty_vec(inner),
+        }
+    }
+}
+
+impl<S> Clone for SharedVecStorage<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<S> Default for SharedVecStorage<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Implements `DerefMut<Target = C>`, and puts the allocation back in place
+/// in the source `SharedVecStorage<S>` once dropped
+pub struct OwnedStorageReuse<C: ReusableContainer, S> {
+    storage: Arc<Mutex<VecStorageForReuse<S>>>,
+    inner: C,
+}
+
+/// Implements `DerefMut<Target = Vec<T>>`, and puts the allocation back in place
+/// in the source `SharedVecStorage<S>` once dropped
+pub type OwnedVecStorageReuse<T, S> = OwnedStorageReuse<Vec<T>, S>;
+
+impl<C: ReusableContainer, S> Drop for OwnedStorageReuse<C, S> {
+    fn drop(&mut self) {
+        let inner = mem::replace(&mut self.inner, C::from_empty_vec(Vec::new())).into_empty_vec();
+        let returned: Vec<S> = recycle::recycle(inner);
+        let mut storage = lock(&self.storage);
+        if returned.capacity() > storage.inner.capacity() {
+            storage.inner = returned;
+        }
+    }
+}
+
+impl<C: ReusableContainer, S> Deref for OwnedStorageReuse<C, S> {
+    type Target = C;
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+impl<C: ReusableContainer, S> DerefMut for OwnedStorageReuse<C, S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+/// Storages only hold empty `Vec`s, so they can't be left in an inconsistent state by a panic
+pub(crate) fn lock<T>(storage: &Mutex<T>) -> MutexGuard<'_, T> {
+    storage.lock().unwrap_or_else(PoisonError::into_inner)
+}
@@ -0,0 +1,181 @@
+use crate::{columns::ColumnBuffer, recycle, LayoutCheck, VecStorageForReuse};
+
+use std::{
+    fmt,
+    marker::PhantomData,
+    mem,
+    ops::{Deref, DerefMut, Drop},
+    ptr::NonNull,
+};
+
+impl<S> VecStorageForReuse<S> {
+    /// Splits the inner `Vec<S>` storage into a `SplitVec<T>` with room for (up to) `capacity_t` values and a
+    /// `SplitVec<U>` using the rest of the allocation, and calls `f` with both
+    ///
+    /// A `SplitVec` that outgrows its part of the allocation moves its values to an allocation of its own. When `f`
+    /// returns, the largest allocation is kept in the storage, provided it has the alignment of `S`.
+    ///
+    /// `T` and `U` must not have a greater alignment than `S`, which is checked at compile time.
+    /// ```
+    /// # use vec_storage_reuse::VecStorageForReuse;
+    /// let mut storage: VecStorageForReuse<&'static str> = VecStorageForReuse::with_capacity(64);
+    ///
+    /// for line in ["a b c", "d e"] {
+    ///     let line = line.to_owned(); // only lives this scope
+    ///     storage.reuse_split::<&str, usize, _>(16, |words, lengths| {
+    ///         words.extend(line.split(' '));
+    ///         lengths.extend(words.iter().map(|word| word.len()));
+    ///         assert!(!words.is_spilled() && !lengths.is_spilled());
+    ///     });
+    /// }
+    /// ```
+    ///
+    /// A `SplitVec` that spilled leaves its allocation in the storage if it is the largest one:
+    /// ```
+    /// # use vec_storage_reuse::VecStorageForReuse;
+    /// let mut storage: VecStorageForReuse<&'static str> = VecStorageForReuse::with_capacity(4);
+    ///
+    /// let line = "a b".to_owned();
+    /// let spilled_capacity = storage.reuse_split::<&str, usize, _>(2, |words, lengths| {
+    ///     words.extend(line.split(' '));
+    ///     lengths.extend(0..10);
+    ///     assert!(!words.is_spilled() && lengths.is_spilled());
+    ///     lengths.capacity()
+    /// });
+    /// assert_eq!(storage.reuse_allocation::<usize>().capacity(), spilled_capacity);
+    /// ```
+    ///
+    /// The `SplitVec`s are only handed out to a closure because they borrow the storage's allocation, which must not
+    /// be replaced while they could still be used.
+    pub fn reuse_split<T, U, R>(
+        &mut self,
+        capacity_t: usize,
+        f: impl for<'p> FnOnce(&mut SplitVec<'p, T>, &mut SplitVec<'p, U>) -> R,
+    ) -> R {
+        let () = LayoutCheck::<S, T>::ALIGN_FITS;
+        let () = LayoutCheck::<S, U>::ALIGN_FITS;
+        let storage = &mut self.inner;
+        let bytes = storage.capacity() * mem::size_of::<S>();
+        let base = NonNull::new(storage.as_mut_ptr() as *mut u8).unwrap_or(NonNull::dangling());
+
+        let first_capacity = capacity_t.min(bytes.checked_div(mem::size_of::<T>()).unwrap_or(0));
+        let first_bytes = first_capacity * mem::size_of::<T>();
+        // Alignments are powers of two
+        let second_offset = (first_bytes + mem::align_of::<U>() - 1) & !(mem::align_of::<U>() - 1);
+        let second_capacity = bytes
+            .saturating_sub(second_offset)
+            .checked_div(mem::size_of::<U>())
+            .unwrap_or(0);
+
+        // Safety: both parts are in the allocation of the storage, aligned since `S` is at least as aligned as `T`
+        // and `U`, and don't overlap. The storage is borrowed until `split` is dropped.
+        let mut split = Split {
+            first: SplitVec::new(unsafe { ColumnBuffer::from_raw_parts(base, 0, first_capacity) }),
+            second: SplitVec::new(unsafe {
+                ColumnBuffer::from_raw_parts(base, second_offset, second_capacity)
+            }),
+            storage,
+        };
+        f(&mut split.first, &mut split.second)
+    }
+}
+
+/// Drops the values of the `SplitVec`s and keeps the largest allocation, even if the closure panics
+struct Split<'s, S, T, U> {
+    storage: &'s mut Vec<S>,
+    first: SplitVec<'s, T>,
+    second: SplitVec<'s, U>,
+}
+
+impl<S, T, U> Drop for Split<'_, S, T, U> {
+    fn drop(&mut self) {
+        // Both are emptied before the storage may be replaced, since they may point to its allocation
+        let first = self.first.take_spilled();
+        let second = self.second.take_spilled();
+        keep_largest(self.storage, first);
+        keep_largest(self.storage, second);
+    }
+}
+
+fn keep_largest<S, T>(storage: &mut Vec<S>, spilled: Option<Vec<T>>) {
+    if let Some(vec) = spilled {
+        if mem::align_of::<T>() == mem::align_of::<S>()
+            && vec.capacity() * mem::size_of::<T>() > storage.capacity() * mem::size_of::<S>()
+        {
+            *storage = recycle::recycle(vec);
+        }
+    }
+}
+
+/// Vector of `T`s handed out by `VecStorageForReuse::reuse_split`, which uses a part of the storage's allocation
+/// until it needs more room, and then moves to an allocation of its own
+pub struct SplitVec<'p, T> {
+    buffer: ColumnBuffer<'p, T>,
+    /// Makes `'p` invariant, so that `SplitVec`s of different storages can't be swapped
+    _marker: PhantomData<fn(&'p ()) -> &'p ()>,
+}
+
+impl<'p, T> SplitVec<'p, T> {
+    fn new(buffer: ColumnBuffer<'p, T>) -> Self {
+        Self {
+            buffer,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Whether the values were moved to an allocation of their own
+    pub fn is_spilled(&self) -> bool {
+        self.buffer.is_spilled()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    pub fn push(&mut self, value: T) {
+        self.buffer.push(value);
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.buffer.pop()
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.buffer.truncate(len);
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Drops the values, and returns the own allocation if the values were spilled
+    fn take_spilled(&mut self) -> Option<Vec<T>> {
+        self.buffer.take_spilled()
+    }
+}
+
+impl<T> Extend<T> for SplitVec<'_, T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+impl<T> Deref for SplitVec<'_, T> {
+    type Target = [T];
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+impl<T> DerefMut for SplitVec<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buffer
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for SplitVec<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
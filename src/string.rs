@@ -0,0 +1,101 @@
+use crate::{StorageReuse, VecStorageForReuse, VecStorageReuse};
+
+use std::{fmt, io};
+
+/// Stores the allocation of a `String`, so that it can be reused to build other strings
+///
+/// ```
+/// # use vec_storage_reuse::StringStorageForReuse;
+/// use std::fmt::Write;
+///
+/// let mut line_storage = StringStorageForReuse::new();
+/// for event in ["start", "stop"] {
+///     let mut line = line_storage.reuse_allocation();
+///     write!(line, "event: {}", event).unwrap();
+///     assert!(line.starts_with("event"));
+/// }
+/// ```
+pub struct StringStorageForReuse {
+    inner: VecStorageForReuse<u8>,
+}
+
+impl StringStorageForReuse {
+    pub fn new() -> Self {
+        Self {
+            inner: VecStorageForReuse::new(),
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            inner: VecStorageForReuse::with_capacity(capacity),
+        }
+    }
+
+    /// Uses the inner storage to provide a `StringStorageReuse: DerefMut<Target = String>`
+    ///
+    /// This avoids reallocating a new `String`.
+    pub fn reuse_allocation(&mut self) -> StringStorageReuse<'_> {
+        self.inner.reuse_as()
+    }
+
+    pub fn from_string(string_to_use_as_storage: String) -> Self {
+        Self::from(VecStorageForReuse::from_vec(
+            string_to_use_as_storage.into_bytes(),
+        ))
+    }
+
+    pub fn into_inner(self) -> VecStorageForReuse<u8> {
+        self.inner
+    }
+}
+
+impl From<VecStorageForReuse<u8>> for StringStorageForReuse {
+    fn from(storage: VecStorageForReuse<u8>) -> Self {
+        Self { inner: storage }
+    }
+}
+
+impl Default for StringStorageForReuse {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Implements `DerefMut<Target = String>` and `fmt::Write`, and puts the allocation back in place
+/// in the source storage once dropped
+pub type StringStorageReuse<'a> = StorageReuse<'a, String, u8>;
+
+impl fmt::Write for StringStorageReuse<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_str(s)
+    }
+    fn write_char(&mut self, c: char) -> fmt::Result {
+        self.inner.write_char(c)
+    }
+}
+
+/// Writes to the inner `Vec<u8>`
+/// ```
+/// # use vec_storage_reuse::VecStorageForReuse;
+/// use std::io::Write;
+///
+/// let mut storage: VecStorageForReuse<u8> = VecStorageForReuse::new();
+/// let mut buffer = storage.reuse_allocation::<u8>();
+/// write!(buffer, "{}", 42).unwrap();
+/// assert_eq!(&**buffer, b"42");
+/// ```
+impl<S> io::Write for VecStorageReuse<'_, u8, S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        self.inner.write_vectored(bufs)
+    }
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.inner.write_all(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}